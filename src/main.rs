@@ -1,8 +1,164 @@
-#[macro_use]
-extern crate bitflags;
+use std::env;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::process;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 
-mod nes;
+use nes_emulator::nes::cartridge::Cartridge;
+use nes_emulator::nes::screenshot::ImageFormat;
+use nes_emulator::nes::NES;
+
+const USAGE: &str = "Usage: nes_emulator <rom> [--frames N] [--screenshot-frame N]... \
+                     [--screenshot-dir DIR] [--format png|ppm] [--hotkey]";
+
+//Запуск без окна: снимки на заданных кадрах или по Enter в терминале (--hotkey)
+struct Options {
+    rom: PathBuf,
+    frames: Option<u64>,
+    screenshot_frames: Vec<u64>,
+    screenshot_dir: PathBuf,
+    format: ImageFormat,
+    hotkey: bool,
+}
 
 fn main() {
-    println!("Hello, world!");
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    if let Err(e) = run(&options) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom = None;
+    let mut options = Options {
+        rom: PathBuf::new(),
+        frames: None,
+        screenshot_frames: vec![],
+        screenshot_dir: PathBuf::from("."),
+        format: ImageFormat::Png,
+        hotkey: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value", arg))
+        };
+
+        match arg.as_str() {
+            "--frames" => options.frames = Some(parse_number(&value()?)?),
+            "--screenshot-frame" => options.screenshot_frames.push(parse_number(&value()?)?),
+            "--screenshot-dir" => options.screenshot_dir = PathBuf::from(value()?),
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "png" => ImageFormat::Png,
+                    "ppm" => ImageFormat::Ppm,
+                    format => return Err(format!("Unknown format {}", format)),
+                }
+            }
+            "--hotkey" => options.hotkey = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+
+    options.rom = rom.ok_or_else(|| "ROM file is not specified".to_string())?;
+
+    if options.frames.is_none() && options.screenshot_frames.is_empty() && !options.hotkey {
+        return Err("Nothing to do: no frames, screenshots or hotkey".to_string());
+    }
+
+    Ok(options)
+}
+
+fn parse_number(value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} is not a frame number", value))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut nes = NES::new();
+    nes.insert_cartridge(Cartridge::load(&options.rom)?);
+
+    let hotkey = match options.hotkey {
+        true => Some(spawn_hotkey()),
+        false => None,
+    };
+    let last_frame = options
+        .frames
+        .or_else(|| options.screenshot_frames.iter().max().copied());
+
+    let mut frame = 0;
+    loop {
+        nes.run_frame();
+        frame += 1;
+
+        let mut capture = options.screenshot_frames.contains(&frame);
+        let mut stop = last_frame.is_some_and(|last| frame >= last);
+
+        if let Some(keys) = &hotkey {
+            loop {
+                match keys.try_recv() {
+                    Ok(()) => capture = true,
+                    Err(TryRecvError::Empty) => break,
+                    //Ввод закрыт: без лимита кадров работать больше незачем
+                    Err(TryRecvError::Disconnected) => {
+                        stop |= last_frame.is_none();
+                        break;
+                    }
+                }
+            }
+        }
+
+        if capture {
+            save_screenshot(&nes, options, frame)?;
+        }
+        if stop {
+            break;
+        }
+    }
+
+    match &nes.cartridge {
+        Some(cartridge) => cartridge.save(),
+        None => Ok(()),
+    }
+}
+
+fn spawn_hotkey() -> Receiver<()> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for _ in io::stdin().lock().lines() {
+            if sender.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn save_screenshot(nes: &NES, options: &Options, frame: u64) -> Result<(), String> {
+    let name = options
+        .rom
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "screenshot".to_string());
+    let path =
+        options
+            .screenshot_dir
+            .join(format!("{}-{}.{}", name, frame, options.format.extension()));
+
+    let data = nes.screenshot(options.format)?;
+    std::fs::write(&path, data).map_err(|e| e.to_string())?;
+    println!("{}", path.display());
+
+    Ok(())
 }
@@ -0,0 +1,245 @@
+use crate::nes::region::Region;
+
+//Канал дельта-модуляции 2A03 ($4010-$4013). Байты сэмпла читает DMA из $C000-$FFFF
+pub struct Dmc {
+    //Периоды вывода бит в тактах CPU зависят от региона
+    rates: &'static [u16; 16],
+    rate: u8,
+    timer: u16,
+    irq_enabled: bool,
+    looped: bool,
+    pub irq: bool,
+
+    //Регистры $4012/$4013
+    sample_address: u16,
+    sample_length: u16,
+    //Читатель сэмпла
+    address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    //Запрос DMA отправлен, байт еще не пришел
+    fetching: bool,
+
+    //Блок вывода: 7-битный счетчик меняется на 2 за бит
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    level: u8,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        let rates = Region::Ntsc.dmc_rates();
+
+        Dmc {
+            rates,
+            rate: 0,
+            timer: rates[0] - 1,
+            irq_enabled: false,
+            looped: false,
+            irq: false,
+
+            sample_address: 0xC000,
+            sample_length: 1,
+            address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            fetching: false,
+
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            level: 0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.rates = region.dmc_rates();
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0b1000_0000 != 0;
+                self.looped = value & 0b0100_0000 != 0;
+                self.rate = value & 0x0F;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = value & 0x7F,
+            2 => self.sample_address = 0xC000 | (value as u16) << 6,
+            3 => self.sample_length = ((value as u16) << 4) + 1,
+            _ => {}
+        }
+    }
+
+    //Бит 4 $4015: выключение останавливает чтение, включение перезапускает
+    //сэмпл, только если он уже закончился. Запись всегда сбрасывает IRQ
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+
+        match enabled {
+            true if self.bytes_remaining == 0 => self.restart(),
+            true => {}
+            false => self.bytes_remaining = 0,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    //Адрес следующего байта, если буфер пуст. Возвращается один раз на байт
+    pub fn fetch_address(&mut self) -> Option<u16> {
+        if self.buffer.is_some() || self.bytes_remaining == 0 || self.fetching {
+            return None;
+        }
+
+        self.fetching = true;
+        Some(self.address)
+    }
+
+    pub fn fill_buffer(&mut self, value: u8) {
+        self.fetching = false;
+        if self.bytes_remaining == 0 {
+            return;
+        }
+
+        self.buffer = Some(value);
+        //После $FFFF чтение продолжается с $8000
+        self.address = self.address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            match (self.looped, self.irq_enabled) {
+                (true, _) => self.restart(),
+                (false, true) => self.irq = true,
+                (false, false) => {}
+            }
+        }
+    }
+
+    //Таймер идет каждый такт CPU
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.rates[self.rate as usize] - 1;
+                self.clock_output();
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    fn clock_output(&mut self) {
+        if !self.silence {
+            match self.shift & 1 != 0 {
+                true if self.level <= 125 => self.level += 2,
+                false if self.level >= 2 => self.level -= 2,
+                _ => {}
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(value) => {
+                    self.shift = value;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Dmc::new()
+    }
+}
+
+#[cfg(test)]
+mod dmc_test {
+    use super::*;
+
+    #[test]
+    fn test_sample_reader() {
+        let mut dmc = Dmc::new();
+        dmc.write(2, 0xFF);
+        dmc.write(3, 0x00);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.fetch_address(), Some(0xFFC0));
+        assert_eq!(dmc.fetch_address(), None);
+        dmc.fill_buffer(0xFF);
+        assert!(!dmc.active());
+        assert!(!dmc.irq);
+
+        //Переход через $FFFF и IRQ в конце сэмпла
+        dmc.write(0, 0x80);
+        dmc.address = 0xFFFF;
+        dmc.bytes_remaining = 2;
+        dmc.buffer = None;
+        assert_eq!(dmc.fetch_address(), Some(0xFFFF));
+        dmc.fill_buffer(0);
+        dmc.buffer = None;
+        assert_eq!(dmc.fetch_address(), Some(0x8000));
+        dmc.fill_buffer(0);
+        assert!(dmc.irq);
+
+        dmc.set_enabled(false);
+        assert!(!dmc.irq);
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0xC0);
+        dmc.write(2, 0x01);
+        dmc.set_enabled(true);
+
+        dmc.fetch_address();
+        dmc.fill_buffer(0);
+        assert!(dmc.active());
+        assert!(!dmc.irq);
+        assert_eq!(dmc.address, 0xC040);
+    }
+
+    #[test]
+    fn test_output() {
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0x0F);
+        dmc.write(1, 0x7E);
+        dmc.set_enabled(true);
+        dmc.fetch_address();
+        dmc.fill_buffer(0b0000_0101);
+        dmc.timer = 0;
+
+        //Первые 8 бит - тишина, пока буфер не попал в сдвиговый регистр
+        let period = Region::Ntsc.dmc_rates()[0x0F] as usize;
+        for _ in 0..period * 8 {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 0x7E);
+
+        //Уровень не выходит за 127
+        dmc.clock_timer();
+        assert_eq!(dmc.output(), 0x7E);
+        for _ in 0..period {
+            dmc.clock_timer();
+        }
+        assert_eq!(dmc.output(), 0x7C);
+    }
+}
@@ -0,0 +1,81 @@
+//Огибающая громкости: постоянная громкость или спад 15..0 с периодом V+1 четвертей кадра
+#[derive(Default)]
+pub struct Envelope {
+    start: bool,
+    divider: u8,
+    decay: u8,
+    looped: bool,
+    constant_volume: bool,
+    volume: u8,
+}
+
+impl Envelope {
+    //Биты 0-5 регистров $4000/$4004/$400C: громкость/период, постоянная громкость, повтор
+    pub fn write(&mut self, value: u8) {
+        self.looped = value & 0b0010_0000 != 0;
+        self.constant_volume = value & 0b0001_0000 != 0;
+        self.volume = value & 0x0F;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looped {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        match self.constant_volume {
+            true => self.volume,
+            false => self.decay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod envelope_test {
+    use super::*;
+
+    #[test]
+    fn test_decay() {
+        let mut envelope = Envelope::default();
+        envelope.write(0x01);
+        envelope.restart();
+
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+
+        //Период делителя - два такта
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+        envelope.clock();
+        assert_eq!(envelope.output(), 14);
+
+        for _ in 0..28 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+
+        envelope.write(0x21);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+
+        envelope.write(0x17);
+        assert_eq!(envelope.output(), 7);
+    }
+}
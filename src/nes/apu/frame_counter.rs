@@ -0,0 +1,182 @@
+use crate::nes::region::Region;
+
+//Что счетчик кадров тактирует в текущем такте CPU
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameStep {
+    None,
+    //Огибающие и линейный счетчик
+    Quarter,
+    //То же и счетчики длины со свипами
+    Half,
+}
+
+//Счетчик кадров APU ($4017): 4 или 5 шагов, IRQ в конце 4-шагового цикла
+pub struct FrameCounter {
+    //Такты шагов зависят от региона
+    steps: &'static [u32; 5],
+    five_step: bool,
+    irq_inhibit: bool,
+    pub irq: bool,
+    cycle: u32,
+    //Запись $4017 сбрасывает счетчик через 3 или 4 такта: новый режим и задержка
+    pending: Option<(bool, u8)>,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter {
+            steps: Region::Ntsc.frame_counter_steps(),
+            five_step: false,
+            irq_inhibit: false,
+            irq: false,
+            cycle: 0,
+            pending: None,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.steps = region.frame_counter_steps();
+    }
+
+    //Запрет IRQ действует сразу, режим - после сброса. Запись между тактами APU
+    //(нечетный такт CPU) ждет на такт дольше
+    pub fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.irq_inhibit = value & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+
+        let delay = match odd_cycle {
+            true => 4,
+            false => 3,
+        };
+        self.pending = Some((value & 0b1000_0000 != 0, delay));
+    }
+
+    pub fn clock(&mut self) -> FrameStep {
+        if let Some((five_step, delay)) = self.pending {
+            match delay {
+                1 => {
+                    self.pending = None;
+                    self.five_step = five_step;
+                    self.cycle = 0;
+
+                    //Сброс в 5-шаговый режим сразу тактирует все блоки
+                    return match five_step {
+                        true => FrameStep::Half,
+                        false => FrameStep::None,
+                    };
+                }
+                _ => self.pending = Some((five_step, delay - 1)),
+            }
+        }
+
+        self.cycle += 1;
+        let last = match self.five_step {
+            true => self.steps[4],
+            false => self.steps[3],
+        };
+
+        //4-шаговый режим выставляет IRQ три такта подряд вокруг последнего шага
+        if !self.five_step && !self.irq_inhibit && self.cycle + 1 >= last {
+            self.irq = true;
+        }
+
+        let step = match self.cycle {
+            cycle if cycle == self.steps[0] || cycle == self.steps[2] => FrameStep::Quarter,
+            cycle if cycle == self.steps[1] || cycle == last => FrameStep::Half,
+            _ => FrameStep::None,
+        };
+
+        if self.cycle > last {
+            self.cycle = 0;
+        }
+
+        step
+    }
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        FrameCounter::new()
+    }
+}
+
+#[cfg(test)]
+mod frame_counter_test {
+    use super::*;
+
+    //Такты (от записи $4017) с событиями счетчика и такты установки IRQ
+    fn run(counter: &mut FrameCounter, cycles: u32) -> (Vec<(u32, FrameStep)>, Vec<u32>) {
+        let mut steps = vec![];
+        let mut irqs = vec![];
+
+        for cycle in 1..=cycles {
+            let step = counter.clock();
+            if step != FrameStep::None {
+                steps.push((cycle, step));
+            }
+            if counter.irq {
+                irqs.push(cycle);
+                counter.irq = false;
+            }
+        }
+
+        (steps, irqs)
+    }
+
+    #[test]
+    fn test_four_step() {
+        let mut counter = FrameCounter::new();
+        counter.write(0x00, false);
+
+        let (steps, irqs) = run(&mut counter, 29833);
+        assert_eq!(
+            steps,
+            [
+                (7460, FrameStep::Quarter),
+                (14916, FrameStep::Half),
+                (22374, FrameStep::Quarter),
+                (29832, FrameStep::Half),
+            ]
+        );
+        assert_eq!(irqs, [29831, 29832, 29833]);
+
+        //Следующий цикл идет без задержки записи
+        let (steps, _) = run(&mut counter, 7457);
+        assert_eq!(steps, [(7457, FrameStep::Quarter)]);
+    }
+
+    #[test]
+    fn test_five_step() {
+        let mut counter = FrameCounter::new();
+        counter.set_region(Region::Pal);
+        counter.write(0x80, true);
+
+        let (steps, irqs) = run(&mut counter, 41570);
+        assert_eq!(
+            steps,
+            [
+                (4, FrameStep::Half),
+                (8317, FrameStep::Quarter),
+                (16631, FrameStep::Half),
+                (24943, FrameStep::Quarter),
+                (41569, FrameStep::Half),
+            ]
+        );
+        assert!(irqs.is_empty());
+    }
+
+    #[test]
+    fn test_irq_inhibit() {
+        let mut counter = FrameCounter::new();
+        counter.write(0x00, false);
+        run(&mut counter, 29831);
+        counter.irq = true;
+
+        counter.write(0x40, false);
+        assert!(!counter.irq);
+        let (_, irqs) = run(&mut counter, 29833);
+        assert!(irqs.is_empty());
+    }
+}
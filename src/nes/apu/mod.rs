@@ -0,0 +1,252 @@
+mod dmc;
+mod envelope;
+mod frame_counter;
+mod length_counter;
+mod noise;
+mod pulse;
+mod triangle;
+
+use crate::nes::apu::dmc::Dmc;
+use crate::nes::apu::frame_counter::{FrameCounter, FrameStep};
+use crate::nes::apu::noise::Noise;
+use crate::nes::apu::pulse::Pulse;
+use crate::nes::apu::triangle::Triangle;
+use crate::nes::region::Region;
+
+//Звуковой блок 2A03: регистры $4000-$4013, $4015 и $4017
+pub struct APU {
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    //Таймеры прямоугольных каналов идут на каждом втором такте CPU
+    odd_cycle: bool,
+}
+
+#[allow(dead_code)]
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            pulses: [Pulse::new(true), Pulse::new(false)],
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            odd_cycle: false,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+        self.frame_counter.set_region(region);
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulses[0].write(address & 0b11, value),
+            0x4004..=0x4007 => self.pulses[1].write(address & 0b11, value),
+            0x4008..=0x400B => self.triangle.write(address & 0b11, value),
+            0x400C..=0x400F => self.noise.write(address & 0b11, value),
+            0x4010..=0x4013 => self.dmc.write(address & 0b11, value),
+            0x4015 => {
+                self.pulses[0].length.set_enabled(value & 0b0001 != 0);
+                self.pulses[1].length.set_enabled(value & 0b0010 != 0);
+                self.triangle.length.set_enabled(value & 0b0100 != 0);
+                self.noise.length.set_enabled(value & 0b1000 != 0);
+                self.dmc.set_enabled(value & 0b1_0000 != 0);
+            }
+            0x4017 => self.frame_counter.write(value, self.odd_cycle),
+            _ => {}
+        }
+    }
+
+    //Один такт CPU
+    pub fn clock(&mut self) {
+        match self.frame_counter.clock() {
+            FrameStep::Quarter => self.quarter_frame(),
+            FrameStep::Half => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            FrameStep::None => {}
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulses.iter_mut().for_each(Pulse::clock_timer);
+        }
+    }
+
+    //Адрес байта сэмпла DMC, который нужно прочитать через DMA
+    pub fn dmc_fetch(&mut self) -> Option<u16> {
+        self.dmc.fetch_address()
+    }
+
+    pub fn dmc_sample(&mut self, value: u8) {
+        self.dmc.fill_buffer(value);
+    }
+
+    //Линия IRQ процессора
+    pub fn irq(&self) -> bool {
+        self.dmc.irq || self.frame_counter.irq
+    }
+
+    //$4015: активные каналы и флаги IRQ. Чтение сбрасывает IRQ счетчика кадров.
+    //Бит 5 не выставляется, его берут с открытой шины
+    pub fn read_status(&mut self) -> u8 {
+        let mut value = 0;
+        for (bit, active) in [
+            self.pulses[0].length.active(),
+            self.pulses[1].length.active(),
+            self.triangle.length.active(),
+            self.noise.length.active(),
+            self.dmc.active(),
+        ]
+        .iter()
+        .enumerate()
+        {
+            value |= (*active as u8) << bit;
+        }
+        value |= (self.frame_counter.irq as u8) << 6;
+        value |= (self.dmc.irq as u8) << 7;
+
+        self.frame_counter.irq = false;
+        value
+    }
+
+    //Четверть кадра: огибающие и линейный счетчик
+    fn quarter_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.envelope.clock();
+        }
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    //Половина кадра: счетчики длины и свипы
+    fn half_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.length.clock();
+            pulse.clock_sweep();
+        }
+        self.triangle.length.clock();
+        self.noise.length.clock();
+    }
+
+    //Нелинейный микшер 2A03, выход 0.0..1.0. Звук картриджа (expansion) уже в той же
+    //шкале и на плате подмешивается линейно
+    pub fn output(&self, expansion: f32) -> f32 {
+        let pulse = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+
+        let pulse_out = match pulse > 0.0 {
+            true => 95.88 / (8128.0 / pulse + 100.0),
+            false => 0.0,
+        };
+        let tnd_out = match tnd > 0.0 {
+            true => 159.79 / (1.0 / tnd + 100.0),
+            false => 0.0,
+        };
+
+        pulse_out + tnd_out + expansion
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU::new()
+    }
+}
+
+#[cfg(test)]
+mod apu_test {
+    use super::*;
+
+    #[test]
+    fn test_pulse_output() {
+        let mut apu = APU::new();
+        //Остановленный треугольный канал держит шаг 0 - постоянный уровень 15
+        let idle = apu.output(0.0);
+        assert!((idle - 159.79 / (8227.0 / 15.0 + 100.0)).abs() < 1e-6);
+
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4002, 0x08);
+        apu.write_register(0x4003, 0x08);
+
+        //Скважность 50%: звучат шаги 1-4 последовательности
+        let mut levels = vec![];
+        for _ in 0..8 * 18 {
+            apu.clock();
+            levels.push(apu.output(0.0) - idle);
+        }
+        assert!(levels.contains(&0.0));
+        assert!(levels
+            .iter()
+            .any(|&level| (level - 95.88 / (8128.0 / 15.0 + 100.0)).abs() < 1e-6));
+
+        assert_eq!(apu.output(0.25), apu.output(0.0) + 0.25);
+
+        //Канал 2 выключен - запись длины игнорируется
+        apu.write_register(0x4007, 0x08);
+        assert!(!apu.pulses[1].length.active());
+    }
+
+    #[test]
+    fn test_status() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x0F);
+        apu.write_register(0x4003, 0x08);
+        apu.write_register(0x400F, 0x08);
+        assert_eq!(apu.read_status(), 0b0000_1001);
+
+        //IRQ счетчика кадров сбрасывается чтением, IRQ DMC - нет
+        apu.frame_counter.irq = true;
+        apu.dmc.irq = true;
+        assert!(apu.irq());
+        assert_eq!(apu.read_status(), 0b1100_1001);
+        assert_eq!(apu.read_status(), 0b1000_1001);
+    }
+
+    #[test]
+    fn test_frame_counter() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x01);
+        apu.write_register(0x4000, 0b0001_1111);
+        apu.write_register(0x4003, 0x18);
+
+        //Длина 2: два полукадра 5-шагового режима, первый - сразу после записи
+        apu.write_register(0x4017, 0x80);
+        for _ in 0..4 {
+            apu.clock();
+        }
+        assert!(apu.pulses[0].length.active());
+        for _ in 0..14913 {
+            apu.clock();
+        }
+        assert!(!apu.pulses[0].length.active());
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_channel_enable() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0x0C);
+        apu.write_register(0x400B, 0x08);
+        apu.write_register(0x400F, 0x08);
+        assert!(apu.triangle.length.active());
+        assert!(apu.noise.length.active());
+
+        apu.write_register(0x4015, 0x00);
+        assert!(!apu.triangle.length.active());
+        assert!(!apu.noise.length.active());
+    }
+}
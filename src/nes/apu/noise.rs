@@ -0,0 +1,121 @@
+use crate::nes::apu::envelope::Envelope;
+use crate::nes::apu::length_counter::LengthCounter;
+use crate::nes::region::Region;
+
+//Шумовой канал 2A03 ($400C-$400F)
+pub struct Noise {
+    //Периоды в тактах CPU зависят от региона
+    periods: &'static [u16; 16],
+    period: u8,
+    timer: u16,
+    //Короткий режим: обратная связь от бита 6, последовательность из 93 шагов
+    short_mode: bool,
+    shift: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            periods: Region::Ntsc.noise_periods(),
+            period: 0,
+            timer: 0,
+            short_mode: false,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.periods = region.noise_periods();
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length.halt = value & 0b0010_0000 != 0;
+                self.envelope.write(value);
+            }
+            2 => {
+                self.short_mode = value & 0b1000_0000 != 0;
+                self.period = value & 0x0F;
+            }
+            3 => {
+                self.length.load(value);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    //Таймер идет каждый такт CPU
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.periods[self.period as usize] - 1;
+                self.clock_shift();
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    //15-битный сдвиговый регистр с обратной связью
+    fn clock_shift(&mut self) {
+        let tap = match self.short_mode {
+            true => 6,
+            false => 1,
+        };
+        let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+        self.shift = (self.shift >> 1) | (feedback << 14);
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            return 0;
+        }
+
+        self.envelope.output()
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise::new()
+    }
+}
+
+#[cfg(test)]
+mod noise_test {
+    use super::*;
+
+    fn sequence_length(noise: &mut Noise) -> usize {
+        let start = noise.shift;
+        (1..=0x8000)
+            .find(|_| {
+                noise.clock_shift();
+                noise.shift == start
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_lfsr() {
+        let mut noise = Noise::new();
+        assert_eq!(sequence_length(&mut noise), 32767);
+
+        noise.write(2, 0x80);
+        assert_eq!(sequence_length(&mut noise), 93);
+    }
+
+    #[test]
+    fn test_period() {
+        let mut noise = Noise::new();
+        noise.write(2, 0x0F);
+        noise.set_region(Region::Pal);
+
+        noise.clock_timer();
+        assert_eq!(noise.timer, 3777);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::nes::apu::envelope::Envelope;
+use crate::nes::apu::length_counter::LengthCounter;
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+//Прямоугольный канал 2A03 ($4000-$4003 и $4004-$4007)
+pub struct Pulse {
+    //Первый канал вычитает в обратном коде: сдвиг вниз на 1 больше, чем у второго
+    ones_complement: bool,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_reload: bool,
+    sweep_divider: u8,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_reload: false,
+            sweep_divider: 0,
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length.halt = value & 0b0010_0000 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0b1000_0000 != 0;
+                self.sweep_period = (value >> 4) & 0b111;
+                self.sweep_negate = value & 0b0000_1000 != 0;
+                self.sweep_shift = value & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.length.load(value);
+                self.step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    //Таймер идет каждый второй такт CPU
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period;
+                self.step = (self.step + 1) & 0b111;
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.timer_period = self.target_period();
+        }
+
+        match self.sweep_divider == 0 || self.sweep_reload {
+            true => {
+                self.sweep_divider = self.sweep_period;
+                self.sweep_reload = false;
+            }
+            false => self.sweep_divider -= 1,
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+
+        match (self.sweep_negate, self.ones_complement) {
+            (false, _) => self.timer_period + change,
+            (true, true) => self.timer_period.saturating_sub(change + 1),
+            (true, false) => self.timer_period.saturating_sub(change),
+        }
+    }
+
+    //Период меньше 8 или переполнение цели свипа глушат канал, даже если свип выключен
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x07FF
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.step as usize] == 0
+        {
+            return 0;
+        }
+
+        self.envelope.output()
+    }
+}
+
+#[cfg(test)]
+mod pulse_test {
+    use super::*;
+
+    fn pulse(ones_complement: bool, period: u16, sweep: u8) -> Pulse {
+        let mut pulse = Pulse::new(ones_complement);
+        pulse.length.set_enabled(true);
+        pulse.write(0, 0b1011_1111);
+        pulse.write(1, sweep);
+        pulse.write(2, period as u8);
+        pulse.write(3, (period >> 8) as u8);
+        pulse
+    }
+
+    #[test]
+    fn test_sweep_negate() {
+        //Сдвиг 1, уменьшение, период делителя 0
+        let mut first = pulse(true, 0x100, 0b1000_1001);
+        let mut second = pulse(false, 0x100, 0b1000_1001);
+
+        first.clock_sweep();
+        second.clock_sweep();
+        assert_eq!(first.timer_period, 0x7F);
+        assert_eq!(second.timer_period, 0x80);
+    }
+
+    #[test]
+    fn test_mute() {
+        let mut pulse = pulse(false, 0x100, 0);
+        pulse.step = 1;
+        assert_eq!(pulse.output(), 15);
+
+        pulse.write(2, 0x07);
+        pulse.write(3, 0x00);
+        pulse.step = 1;
+        assert_eq!(pulse.output(), 0);
+
+        //Цель свипа больше $7FF глушит канал даже при выключенном свипе
+        let mut pulse = self::pulse(false, 0x600, 0b0000_0001);
+        pulse.step = 1;
+        assert_eq!(pulse.output(), 0);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut pulse = pulse(false, 0x100, 0);
+        pulse.write(0, 0b1001_1111);
+        pulse.write(3, 0x19);
+        pulse.step = 1;
+
+        //Индекс 3 - длина 2
+        pulse.length.clock();
+        assert_eq!(pulse.output(), 15);
+        pulse.length.clock();
+        assert_eq!(pulse.output(), 0);
+
+        pulse.length.set_enabled(false);
+        pulse.write(3, 0x19);
+        assert!(!pulse.length.active());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::nes::apu::length_counter::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+//Треугольный канал 2A03 ($4008-$400B)
+pub struct Triangle {
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    pub length: LengthCounter,
+
+    //Линейный счетчик: второй таймер длины с шагом в четверть кадра
+    control: bool,
+    linear_reload_value: u8,
+    linear_reload: bool,
+    linear_counter: u8,
+}
+
+impl Triangle {
+    pub fn new() -> Self {
+        Triangle {
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            length: LengthCounter::default(),
+
+            control: false,
+            linear_reload_value: 0,
+            linear_reload: false,
+            linear_counter: 0,
+        }
+    }
+
+    pub fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.control = value & 0b1000_0000 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = value & 0b0111_1111;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.length.load(value);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    //Таймер идет каждый такт CPU
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period;
+                //Периоды 0 и 1 дают ультразвук, который на выходе только щелкает.
+                //Последовательность стоит на месте, как делают большинство эмуляторов
+                if self.length.active() && self.linear_counter > 0 && self.timer_period >= 2 {
+                    self.step = (self.step + 1) & 0x1F;
+                }
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_linear(&mut self) {
+        match self.linear_reload {
+            true => self.linear_counter = self.linear_reload_value,
+            false => self.linear_counter = self.linear_counter.saturating_sub(1),
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    //Остановленный канал продолжает выдавать текущий шаг, а не 0
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
+
+impl Default for Triangle {
+    fn default() -> Self {
+        Triangle::new()
+    }
+}
+
+#[cfg(test)]
+mod triangle_test {
+    use super::*;
+
+    fn triangle(period: u16) -> Triangle {
+        let mut triangle = Triangle::new();
+        triangle.length.set_enabled(true);
+        triangle.write(0, 0x04);
+        triangle.write(2, period as u8);
+        triangle.write(3, 0x08 | (period >> 8) as u8);
+        triangle.clock_linear();
+        triangle
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut triangle = triangle(0);
+        triangle.write(2, 0x02);
+
+        let mut levels = vec![];
+        for _ in 0..32 * 3 {
+            triangle.clock_timer();
+            if triangle.timer == 0 {
+                levels.push(triangle.output());
+            }
+        }
+        assert_eq!(levels[..17], SEQUENCE[1..18]);
+    }
+
+    #[test]
+    fn test_linear_counter() {
+        let mut triangle = triangle(0x10);
+
+        //Флаг управления сброшен: счетчик перезагружается один раз
+        for _ in 0..4 {
+            triangle.clock_linear();
+        }
+        assert_eq!(triangle.linear_counter, 0);
+
+        let step = triangle.step;
+        for _ in 0..0x100 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.step, step);
+
+        //Флаг установлен: перезагрузка каждую четверть кадра
+        triangle.write(0, 0x84);
+        triangle.write(3, 0x08);
+        triangle.clock_linear();
+        triangle.clock_linear();
+        assert_eq!(triangle.linear_counter, 4);
+    }
+
+    #[test]
+    fn test_ultrasonic() {
+        let mut triangle = triangle(1);
+        let output = triangle.output();
+
+        for _ in 0..100 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), output);
+    }
+}
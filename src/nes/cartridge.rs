@@ -0,0 +1,464 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::nes::mapper::registry::MapperRegistry;
+use crate::nes::mapper::{Mapper, Mirroring};
+use crate::nes::region::Region;
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const UNIF_TAG: [u8; 4] = [0x55, 0x4E, 0x49, 0x46];
+const HEADER_SIZE: usize = 16;
+const UNIF_HEADER_SIZE: usize = 32;
+const UNIF_CHUNK_HEADER_SIZE: usize = 8;
+const TRAINER_SIZE: usize = 512;
+
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+const PRG_RAM_PAGE_SIZE: usize = 0x2000;
+
+#[allow(dead_code)]
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u16,
+    pub submapper: u8,
+    //Имя платы из файла UNIF, у iNES номера маппера достаточно
+    pub board: Option<String>,
+    pub mirroring: Mirroring,
+    //Бит 0 байта 6 как есть: при четырех экранах он теряется в mirroring,
+    //а маппер 30 по нему выбирает между одним и четырьмя экранами
+    pub mirroring_bit: bool,
+    pub battery: bool,
+    pub nes2: bool,
+    pub region: Region,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+}
+
+#[allow(dead_code)]
+impl Rom {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        if data.len() >= UNIF_HEADER_SIZE && data[0..4] == UNIF_TAG {
+            return Rom::from_unif(data);
+        }
+
+        if data.len() < HEADER_SIZE || data[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        let flags_6 = data[6];
+        let flags_7 = data[7];
+        let nes2 = flags_7 & 0b0000_1100 == 0b0000_1000;
+
+        let mirroring = match (flags_6 & 0b1000 != 0, flags_6 & 0b1 != 0) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+
+        let mut mapper = ((flags_7 & 0xF0) | (flags_6 >> 4)) as u16;
+        let mut submapper = 0;
+
+        let region;
+        let prg_rom_size;
+        let chr_rom_size;
+        let prg_ram_size;
+        let prg_nvram_size;
+        let chr_ram_size;
+        let chr_nvram_size;
+
+        if nes2 {
+            mapper |= ((data[8] & 0x0F) as u16) << 8;
+            submapper = data[8] >> 4;
+            region = Region::from_nes2_timing(data[12]);
+
+            prg_rom_size = rom_size(data[4], data[9] & 0x0F, PRG_ROM_PAGE_SIZE)?;
+            chr_rom_size = rom_size(data[5], data[9] >> 4, CHR_ROM_PAGE_SIZE)?;
+            prg_ram_size = ram_size(data[10] & 0x0F);
+            prg_nvram_size = ram_size(data[10] >> 4);
+            chr_ram_size = ram_size(data[11] & 0x0F);
+            chr_nvram_size = ram_size(data[11] >> 4);
+        } else {
+            //Бит PAL в байте 9 почти никто не ставит, но другого источника нет
+            region = match data[9] & 0x01 {
+                0 => Region::Ntsc,
+                _ => Region::Pal,
+            };
+            prg_rom_size = data[4] as usize * PRG_ROM_PAGE_SIZE;
+            chr_rom_size = data[5] as usize * CHR_ROM_PAGE_SIZE;
+
+            //Старые дампы не указывают размер PRG RAM, считаем что он минимум 8 KiB
+            let ram = PRG_RAM_PAGE_SIZE * std::cmp::max(data[8] as usize, 1);
+            match flags_6 & 0b10 != 0 {
+                true => {
+                    prg_ram_size = 0;
+                    prg_nvram_size = ram;
+                }
+                false => {
+                    prg_ram_size = ram;
+                    prg_nvram_size = 0;
+                }
+            }
+
+            chr_ram_size = match chr_rom_size {
+                0 => CHR_ROM_PAGE_SIZE,
+                _ => 0,
+            };
+            chr_nvram_size = 0;
+        }
+
+        let trainer_size = match flags_6 & 0b100 != 0 {
+            true => TRAINER_SIZE,
+            false => 0,
+        };
+
+        let prg_rom_start = HEADER_SIZE + trainer_size;
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+
+        match chr_rom_start.checked_add(chr_rom_size) {
+            Some(end) if end <= data.len() => {}
+            _ => return Err("ROM file is truncated".to_string()),
+        }
+
+        Ok(Rom {
+            prg_rom: data[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: data[chr_rom_start..chr_rom_start + chr_rom_size].to_vec(),
+            mapper,
+            submapper,
+            board: None,
+            mirroring,
+            mirroring_bit: flags_6 & 0b1 != 0,
+            battery: flags_6 & 0b10 != 0,
+            nes2,
+            region,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+        })
+    }
+
+    //UNIF: заголовок 32 байта, дальше блоки "ID(4) длина(4 LE) данные"
+    fn from_unif(data: &[u8]) -> Result<Self, String> {
+        let mut board = None;
+        let mut prg_chunks: Vec<(u8, &[u8])> = vec![];
+        let mut chr_chunks: Vec<(u8, &[u8])> = vec![];
+        let mut mirroring = Mirroring::Horizontal;
+        let mut battery = false;
+        let mut region = Region::Ntsc;
+
+        let mut offset = UNIF_HEADER_SIZE;
+        while offset + UNIF_CHUNK_HEADER_SIZE <= data.len() {
+            let id = &data[offset..offset + 4];
+            let mut length = [0; 4];
+            length.copy_from_slice(&data[offset + 4..offset + 8]);
+            let length = u32::from_le_bytes(length) as usize;
+
+            let start = offset + UNIF_CHUNK_HEADER_SIZE;
+            if data.len() < start + length {
+                return Err("ROM file is truncated".to_string());
+            }
+            let chunk = &data[start..start + length];
+
+            match id {
+                b"MAPR" => {
+                    let name = chunk.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                    board = Some(unif_board_name(&String::from_utf8_lossy(name)));
+                }
+                [b'P', b'R', b'G', index] => prg_chunks.push((*index, chunk)),
+                [b'C', b'H', b'R', index] => chr_chunks.push((*index, chunk)),
+                b"MIRR" if !chunk.is_empty() => {
+                    mirroring = match chunk[0] {
+                        1 => Mirroring::Vertical,
+                        2 => Mirroring::SingleScreenA,
+                        3 => Mirroring::SingleScreenB,
+                        4 => Mirroring::FourScreen,
+                        _ => Mirroring::Horizontal,
+                    }
+                }
+                b"BATR" => battery = chunk.first() != Some(&0),
+                //0 - NTSC, 1 - PAL, 2 - оба
+                b"TVCI" if chunk.first() == Some(&1) => region = Region::Pal,
+                _ => {}
+            }
+
+            offset = start + length;
+        }
+
+        let board = board.ok_or_else(|| "UNIF file has no board name".to_string())?;
+
+        prg_chunks.sort_by_key(|&(index, _)| index);
+        chr_chunks.sort_by_key(|&(index, _)| index);
+        let prg_rom: Vec<u8> = prg_chunks
+            .iter()
+            .flat_map(|(_, chunk)| chunk.to_vec())
+            .collect();
+        let chr_rom: Vec<u8> = chr_chunks
+            .iter()
+            .flat_map(|(_, chunk)| chunk.to_vec())
+            .collect();
+
+        if prg_rom.is_empty() {
+            return Err("UNIF file has no PRG ROM".to_string());
+        }
+
+        let (prg_ram_size, prg_nvram_size) = match battery {
+            true => (0, PRG_RAM_PAGE_SIZE),
+            false => (PRG_RAM_PAGE_SIZE, 0),
+        };
+        let chr_ram_size = match chr_rom.len() {
+            0 => CHR_ROM_PAGE_SIZE,
+            _ => 0,
+        };
+
+        Ok(Rom {
+            prg_rom,
+            chr_rom,
+            mapper: 0,
+            submapper: 0,
+            board: Some(board),
+            mirroring_bit: mirroring == Mirroring::Vertical,
+            mirroring,
+            battery,
+            nes2: false,
+            region,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+impl Rom {
+    //Образ для тестов мапперов: iNES без RAM, вертикальное зеркалирование, NTSC.
+    //Остальные поля тест меняет сам
+    pub fn test(mapper: u16, submapper: u8, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Rom {
+            prg_rom,
+            chr_rom,
+            mapper,
+            submapper,
+            board: None,
+            mirroring: Mirroring::Vertical,
+            mirroring_bit: false,
+            battery: false,
+            nes2: false,
+            region: Region::Ntsc,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+        }
+    }
+}
+
+//Префиксы NES-, HVC-, UNL- и т.п. означают только происхождение платы
+fn unif_board_name(name: &str) -> String {
+    let name = name.trim();
+    let prefixes = [
+        "NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-", "TENGEN-",
+    ];
+
+    prefixes
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+        .to_string()
+}
+
+fn rom_size(lsb: u8, msb: u8, page_size: usize) -> Result<usize, String> {
+    let size = match msb {
+        //Экспоненциальная запись: 2^E * (MM * 2 + 1), до 2^63 * 7 байт
+        0x0F => 1usize
+            .checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul((lsb & 0b11) as usize * 2 + 1)),
+        _ => (((msb as usize) << 8) | lsb as usize).checked_mul(page_size),
+    };
+
+    size.ok_or_else(|| "ROM size in the header is too large".to_string())
+}
+
+fn ram_size(shift: u8) -> usize {
+    match shift {
+        0 => 0,
+        _ => 64 << shift,
+    }
+}
+
+pub struct Cartridge {
+    pub mapper: Box<dyn Mapper>,
+    pub region: Region,
+    save_path: Option<PathBuf>,
+}
+
+#[allow(dead_code)]
+impl Cartridge {
+    pub fn new(rom: Rom) -> Result<Self, String> {
+        Cartridge::with_registry(rom, &MapperRegistry::default())
+    }
+
+    pub fn with_registry(rom: Rom, registry: &MapperRegistry) -> Result<Self, String> {
+        let mapper = registry.create(&rom)?;
+
+        Ok(Cartridge {
+            mapper,
+            region: rom.region,
+            save_path: None,
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Cartridge::load_with_registry(path, &MapperRegistry::default())
+    }
+
+    pub fn load_with_registry<P: AsRef<Path>>(
+        path: P,
+        registry: &MapperRegistry,
+    ) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        let rom = Rom::from_bytes(&data)?;
+
+        let mut cartridge = Cartridge::with_registry(rom, registry)?;
+
+        //Сохранять есть что только у картриджей с батарейкой или EEPROM
+        if cartridge.mapper.save_data().is_some() {
+            let save_path = path.with_extension("sav");
+
+            if let Ok(save) = fs::read(&save_path) {
+                cartridge.mapper.load_save_data(&save);
+            }
+
+            cartridge.save_path = Some(save_path);
+        }
+
+        Ok(cartridge)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        match (&self.save_path, self.mapper.save_data()) {
+            (Some(path), Some(data)) => fs::write(path, data).map_err(|e| e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod cartridge_test {
+    use super::*;
+
+    fn header(flags_6: u8, flags_7: u8) -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 1, flags_6, flags_7];
+        data.resize(HEADER_SIZE, 0);
+        data.resize(HEADER_SIZE + 2 * PRG_ROM_PAGE_SIZE + CHR_ROM_PAGE_SIZE, 0);
+
+        data
+    }
+
+    #[test]
+    fn test_ines_header() {
+        let rom = Rom::from_bytes(&header(0x53, 0x00)).unwrap();
+
+        assert_eq!(rom.mapper, 5);
+        assert!(!rom.nes2);
+        assert!(rom.battery);
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!(rom.chr_rom.len(), 0x2000);
+        assert_eq!(rom.prg_nvram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let mut data = header(0x50, 0x08);
+        data[8] = 0x10;
+        data[10] = 0x70;
+
+        let rom = Rom::from_bytes(&data).unwrap();
+
+        assert!(rom.nes2);
+        assert_eq!(rom.mapper, 5);
+        assert_eq!(rom.submapper, 1);
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_four_screen_mirroring_bit() {
+        let rom = Rom::from_bytes(&header(0x08, 0x00)).unwrap();
+        assert_eq!(rom.mirroring, Mirroring::FourScreen);
+        assert!(!rom.mirroring_bit);
+
+        let rom = Rom::from_bytes(&header(0x09, 0x00)).unwrap();
+        assert_eq!(rom.mirroring, Mirroring::FourScreen);
+        assert!(rom.mirroring_bit);
+    }
+
+    #[test]
+    fn test_region() {
+        let mut data = header(0x00, 0x08);
+        data[12] = 0x03;
+        assert_eq!(Rom::from_bytes(&data).unwrap().region, Region::Dendy);
+
+        let mut data = header(0x00, 0x00);
+        data[9] = 0x01;
+        assert_eq!(Rom::from_bytes(&data).unwrap().region, Region::Pal);
+    }
+
+    #[test]
+    fn test_unif() {
+        let mut data = b"UNIF".to_vec();
+        data.resize(UNIF_HEADER_SIZE, 0);
+
+        let mut chunk = |id: &[u8], body: &[u8]| {
+            data.extend_from_slice(id);
+            data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            data.extend_from_slice(body);
+        };
+        chunk(b"MAPR", b"NES-ELROM\0");
+        chunk(b"PRG1", &[2; 0x4000]);
+        chunk(b"PRG0", &[1; 0x4000]);
+        chunk(b"MIRR", &[1]);
+        chunk(b"BATR", &[1]);
+
+        let rom = Rom::from_bytes(&data).unwrap();
+
+        assert_eq!(rom.board.as_deref(), Some("ELROM"));
+        assert_eq!(rom.prg_rom[0], 1);
+        assert_eq!(rom.prg_rom[0x4000], 2);
+        assert_eq!(rom.chr_ram_size, CHR_ROM_PAGE_SIZE);
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        assert!(rom.battery);
+        assert!(Cartridge::new(rom).is_ok());
+    }
+
+    #[test]
+    fn test_truncated_rom() {
+        let mut data = header(0x00, 0x00);
+        data.truncate(100);
+
+        assert!(Rom::from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn test_exponent_rom_size() {
+        //NES 2.0, PRG ROM 2^5 * 3 = 96 байт
+        let mut data = header(0x00, 0x08);
+        data[4] = 0x15;
+        data[9] = 0x0F;
+        data.truncate(16 + 96 + 0x2000);
+        assert_eq!(Rom::from_bytes(&data).unwrap().prg_rom.len(), 96);
+
+        //2^63 * 7 не помещается в usize
+        data[4] = 0xFF;
+        assert!(Rom::from_bytes(&data).is_err());
+
+        //2^63 помещается, но файл короче
+        data[4] = 0xFC;
+        assert!(Rom::from_bytes(&data).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::nes::instruction::Instruction;
-use crate::nes::interrupt::{Interrupt, InterruptType, BRK_INT};
+use crate::nes::interrupt::{Interrupt, InterruptType, BRK_INT, IRQ_INT, NMI_RESET, RESET_INT};
 use crate::nes::mem::{Memory, Stack};
 
 pub enum AddressingMode {
@@ -45,7 +45,10 @@ pub struct CPU {
     pub register_x: u8,
     pub register_y: u8,
     pub status: CpuFlags,
-    pub memory: [u8; 65536],
+    //Процессор завис на KIL и ждет сброса
+    pub jammed: bool,
+    //Индекс в последнем вычисленном адресе перенес его на другую страницу
+    page_crossed: bool,
 }
 
 //TODO: Сделать название получше
@@ -53,7 +56,9 @@ const STACK_ADDRESS: u16 = 0x0100;
 const STACK_RESET: u16 = 0x01FF;
 
 const CARRY_MASK: u16 = 256;
-const OVERFLOW_MASK: u16 = 128;
+
+//Сброс, NMI, IRQ и BRK
+pub const INTERRUPT_CYCLES: u8 = 7;
 
 impl CPU {
     pub fn new() -> Self {
@@ -64,613 +69,681 @@ impl CPU {
             register_x: 0,
             register_y: 0,
             status: CpuFlags::ONE,
-            memory: [0; 65536],
+            jammed: false,
+            page_crossed: false,
         }
     }
 
-    //TODO: Проверить
+    //Выполняет программу, загруженную с адреса 0, пока PC не выйдет за ее конец
     pub fn execute_commands(&mut self, commands: std::vec::Vec<u8>) {
-        loop {
-            let opcode = commands[self.program_counter as usize];
-            let instruction = Instruction::from_code(opcode);
-            self.inc_program_counter(1);
-
-            match opcode {
-                //ADC
-                0x69 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
-
-                    self.adc(value);
-                }
-                //AND
-                0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+        let mut memory = commands.clone();
+        memory.resize(0x10000, 0);
 
-                    self.set_register(Register::Accumulator, self.accumulator & value);
-                }
-                //AHX
-                0x93 | 0x9F => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-
-                    self.write_u8(
-                        address,
-                        self.accumulator & self.register_x & (address >> 8) as u8,
-                    );
-                }
-                //TODO: Проверить
-                //ALR
-                0x4B => {
-                    let value = self.read_u8(self.program_counter);
-                    self.set_register(Register::Accumulator, self.accumulator & value);
-                    self.lsr_accum();
-                }
-                //ANC
-                0x0B | 0x2B => {
-                    let value = self.read_u8(self.program_counter);
-                    self.set_register(Register::Accumulator, self.accumulator & value);
-                    self.set_carry_flag(self.status.contains(CpuFlags::NEGATIVE));
-                }
-                //TODO: Проверить правильность установки флагов
-                //ARR
-                0x6B => {
-                    let value = self.read_u8(self.program_counter);
-                    self.set_register(Register::Accumulator, self.accumulator & value);
-                    self.ror_accum();
-
-                    let bit_5 = (self.accumulator >> 5) & 1;
-                    let bit_6 = (self.accumulator >> 6) & 1;
-
-                    self.set_carry_flag(bit_6 == 1);
-                    self.set_overflow_flag(bit_5 ^ bit_6 == 1);
-                }
-                //ASL
-                0x0A | 0x06 | 0x16 | 0x0E | 0x1E => {
-                    let addressing_mode = instruction.addressing_mode;
-
-                    match addressing_mode {
-                        AddressingMode::Accumulator => {
-                            self.asl_accum();
-                        }
-                        _ => {
-                            self.asl_mem(addressing_mode);
-                        }
+        while (self.program_counter as usize) < commands.len() && !self.jammed {
+            self.step(&mut memory);
+        }
+    }
+
+    //Состояние после включения или кнопки Reset: вектор $FFFC, стек уменьшается на 3
+    pub fn reset(&mut self, bus: &mut dyn Memory) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        self.set_interrupt_disable_flag(true);
+        self.jammed = false;
+        self.program_counter = bus.read_u16(RESET_INT.vec_addr);
+    }
+
+    //Выполняет одну инструкцию и возвращает число ее тактов
+    pub fn step(&mut self, bus: &mut dyn Memory) -> u8 {
+        if self.jammed {
+            return 1;
+        }
+
+        let opcode = bus.read_u8(self.program_counter);
+        let instruction = Instruction::from_code(opcode);
+        let mut cycles = instruction.cycle;
+        let page_cross_penalty = instruction.page_cross_penalty();
+        self.page_crossed = false;
+        self.inc_program_counter(1);
+
+        match opcode {
+            //ADC
+            0x69 | 0x65 | 0x75 | 0x6D | 0x7D | 0x79 | 0x61 | 0x71 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
+
+                self.adc(value);
+            }
+            //AND
+            0x29 | 0x25 | 0x35 | 0x2D | 0x3D | 0x39 | 0x21 | 0x31 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
+
+                self.set_register(Register::Accumulator, self.accumulator & value);
+            }
+            //AHX
+            0x93 | 0x9F => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+
+                bus.write_u8(
+                    address,
+                    self.accumulator & self.register_x & (address >> 8) as u8,
+                );
+            }
+            //TODO: Проверить
+            //ALR
+            0x4B => {
+                let value = bus.read_u8(self.program_counter);
+                self.set_register(Register::Accumulator, self.accumulator & value);
+                self.lsr_accum();
+            }
+            //ANC
+            0x0B | 0x2B => {
+                let value = bus.read_u8(self.program_counter);
+                self.set_register(Register::Accumulator, self.accumulator & value);
+                self.set_carry_flag(self.status.contains(CpuFlags::NEGATIVE));
+            }
+            //TODO: Проверить правильность установки флагов
+            //ARR
+            0x6B => {
+                let value = bus.read_u8(self.program_counter);
+                self.set_register(Register::Accumulator, self.accumulator & value);
+                self.ror_accum();
+
+                let bit_5 = (self.accumulator >> 5) & 1;
+                let bit_6 = (self.accumulator >> 6) & 1;
+
+                self.set_carry_flag(bit_6 == 1);
+                self.set_overflow_flag(bit_5 ^ bit_6 == 1);
+            }
+            //ASL
+            0x0A | 0x06 | 0x16 | 0x0E | 0x1E => {
+                let addressing_mode = instruction.addressing_mode;
+
+                match addressing_mode {
+                    AddressingMode::Accumulator => {
+                        self.asl_accum();
+                    }
+                    _ => {
+                        self.asl_mem(bus, addressing_mode);
                     }
                 }
-                //TODO: Стоит ли приводить к u16?
-                //AXS
-                0xCB => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
-
-                    let and = self.accumulator & self.register_x;
-                    let result = (and as u16).wrapping_sub(value as u16);
-
-                    self.set_carry_flag(result & CARRY_MASK != 0);
-                    self.set_register(Register::X, result as u8);
-                }
-                //TODO: Стоит ли делать два раза приведение?
-                //BCC
-                0x90 => match self.status.contains(CpuFlags::CARRY) {
-                    true => {}
-                    false => self.branch(),
-                },
-                //BCS
-                0xB0 => match self.status.contains(CpuFlags::CARRY) {
-                    true => self.branch(),
-                    false => {}
-                },
-                //BEQ
-                0xF0 => match self.status.contains(CpuFlags::ZERO) {
-                    true => self.branch(),
-                    false => {}
-                },
-                //BIT
-                0x24 | 0x2C => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
-
-                    self.bit(value);
-                }
-                //BMI
-                0x30 => match self.status.contains(CpuFlags::NEGATIVE) {
-                    true => self.branch(),
-                    false => {}
-                },
-                //BNE
-                0xD0 => match self.status.contains(CpuFlags::ZERO) {
-                    true => {}
-                    false => self.branch(),
-                },
-                //BPL
-                0x10 => match self.status.contains(CpuFlags::NEGATIVE) {
-                    true => {}
-                    false => self.branch(),
-                },
-                //BRK
-                0x00 => {
-                    self.interrupt(BRK_INT);
-                    self.set_break_command_flag(true);
-                }
-                //BVC
-                0x50 => match self.status.contains(CpuFlags::OVERFLOW) {
-                    true => {}
-                    false => self.branch(),
-                },
-                //BVS
-                0x70 => match self.status.contains(CpuFlags::OVERFLOW) {
-                    true => self.branch(),
-                    false => {}
-                },
-                //CLC
-                0x18 => {
-                    self.set_carry_flag(false);
-                }
-                //CLD
-                0xD8 => {
-                    self.set_decimal_mode_flag(false);
-                }
-                //CLI
-                0x58 => {
-                    self.set_interrupt_disable_flag(false);
-                }
-                //CLV
-                0xB8 => {
-                    self.set_overflow_flag(false);
-                }
-                //CMP
-                0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+            }
+            //TODO: Стоит ли приводить к u16?
+            //AXS
+            0xCB => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
+
+                let and = self.accumulator & self.register_x;
+
+                //Перенос как у CMP: выставлен, если заема не было
+                self.set_carry_flag(and >= value);
+                self.set_register(Register::X, and.wrapping_sub(value));
+            }
+            //TODO: Стоит ли делать два раза приведение?
+            //BCC
+            0x90 => match self.status.contains(CpuFlags::CARRY) {
+                true => {}
+                false => cycles += self.branch(bus),
+            },
+            //BCS
+            0xB0 => match self.status.contains(CpuFlags::CARRY) {
+                true => cycles += self.branch(bus),
+                false => {}
+            },
+            //BEQ
+            0xF0 => match self.status.contains(CpuFlags::ZERO) {
+                true => cycles += self.branch(bus),
+                false => {}
+            },
+            //BIT
+            0x24 | 0x2C => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
+
+                self.bit(value);
+            }
+            //BMI
+            0x30 => match self.status.contains(CpuFlags::NEGATIVE) {
+                true => cycles += self.branch(bus),
+                false => {}
+            },
+            //BNE
+            0xD0 => match self.status.contains(CpuFlags::ZERO) {
+                true => {}
+                false => cycles += self.branch(bus),
+            },
+            //BPL
+            0x10 => match self.status.contains(CpuFlags::NEGATIVE) {
+                true => {}
+                false => cycles += self.branch(bus),
+            },
+            //BRK
+            0x00 => {
+                //Байт после BRK пропускается
+                self.inc_program_counter(1);
+                self.interrupt(bus, BRK_INT);
+                return cycles;
+            }
+            //BVC
+            0x50 => match self.status.contains(CpuFlags::OVERFLOW) {
+                true => {}
+                false => cycles += self.branch(bus),
+            },
+            //BVS
+            0x70 => match self.status.contains(CpuFlags::OVERFLOW) {
+                true => cycles += self.branch(bus),
+                false => {}
+            },
+            //CLC
+            0x18 => {
+                self.set_carry_flag(false);
+            }
+            //CLD
+            0xD8 => {
+                self.set_decimal_mode_flag(false);
+            }
+            //CLI
+            0x58 => {
+                self.set_interrupt_disable_flag(false);
+            }
+            //CLV
+            0xB8 => {
+                self.set_overflow_flag(false);
+            }
+            //CMP
+            0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.compare(self.accumulator, value);
-                }
-                //CPX
-                0xE0 | 0xE4 | 0xEC => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.compare(self.accumulator, value);
+            }
+            //CPX
+            0xE0 | 0xE4 | 0xEC => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.compare(self.register_x, value);
-                }
-                //CPY
-                0xC0 | 0xC4 | 0xCC => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.compare(self.register_x, value);
+            }
+            //CPY
+            0xC0 | 0xC4 | 0xCC => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.compare(self.register_y, value);
-                }
-                //TODO: Провярется (value - 1) или value?
-                //DCP
-                0xC7 | 0xD7 | 0xCF | 0xDF | 0xDB | 0xD3 | 0xC3 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address).wrapping_sub(1); //DEC
-
-                    self.write_u8(address, value);
-                    self.compare(self.accumulator, value); //CMP
-                }
-                //DEC
-                0xC6 | 0xD6 | 0xCE | 0xDE => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let mut value = self.read_u8(address);
-
-                    value = self.decrement(value);
-                    self.write_u8(address, value);
-                }
-                //DEX
-                0xCA => {
-                    self.register_x = self.decrement(self.register_x);
-                }
-                //DEY
-                0x88 => {
-                    self.register_y = self.decrement(self.register_y);
-                }
-                //EOR
-                0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.compare(self.register_y, value);
+            }
+            //TODO: Провярется (value - 1) или value?
+            //DCP
+            0xC7 | 0xD7 | 0xCF | 0xDF | 0xDB | 0xD3 | 0xC3 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address).wrapping_sub(1); //DEC
+
+                bus.write_u8(address, value);
+                self.compare(self.accumulator, value); //CMP
+            }
+            //DEC
+            0xC6 | 0xD6 | 0xCE | 0xDE => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let mut value = bus.read_u8(address);
+
+                value = self.decrement(value);
+                bus.write_u8(address, value);
+            }
+            //DEX
+            0xCA => {
+                self.register_x = self.decrement(self.register_x);
+            }
+            //DEY
+            0x88 => {
+                self.register_y = self.decrement(self.register_y);
+            }
+            //EOR
+            0x49 | 0x45 | 0x55 | 0x4D | 0x5D | 0x59 | 0x41 | 0x51 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.set_register(Register::Accumulator, self.accumulator ^ value);
-                }
-                //INC
-                0xE6 | 0xF6 | 0xEE | 0xFE => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let mut value = self.read_u8(address);
-
-                    value = self.increment(value);
-                    self.write_u8(address, value);
-                }
-                //INX
-                0xE8 => {
-                    self.register_x = self.increment(self.register_x);
-                }
-                //INY
-                0xC8 => {
-                    self.register_y = self.increment(self.register_y);
-                }
-                //ISC
-                0xE7 | 0xF7 | 0xEF | 0xFF | 0xFB | 0xE3 | 0xF3 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let mut value = self.read_u8(address).wrapping_add(1);
-
-                    self.write_u8(address, value);
-                    self.sbc(value);
-                }
-                //JMP
-                0x4C | 0x6C => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                self.set_register(Register::Accumulator, self.accumulator ^ value);
+            }
+            //INC
+            0xE6 | 0xF6 | 0xEE | 0xFE => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let mut value = bus.read_u8(address);
+
+                value = self.increment(value);
+                bus.write_u8(address, value);
+            }
+            //INX
+            0xE8 => {
+                self.register_x = self.increment(self.register_x);
+            }
+            //INY
+            0xC8 => {
+                self.register_y = self.increment(self.register_y);
+            }
+            //ISC
+            0xE7 | 0xF7 | 0xEF | 0xFF | 0xFB | 0xE3 | 0xF3 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let mut value = bus.read_u8(address).wrapping_add(1);
+
+                bus.write_u8(address, value);
+                self.sbc(value);
+            }
+            //JMP
+            0x4C | 0x6C => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.program_counter = address;
-                }
-                //JSR
-                0x20 => {
-                    self.push_u16(self.program_counter.wrapping_add(2));
+                self.program_counter = address;
+                return cycles;
+            }
+            //JSR
+            0x20 => {
+                //В стек попадает адрес последнего байта инструкции
+                self.push_u16(bus, self.program_counter.wrapping_add(1));
 
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.program_counter = address;
-                    continue; //Пропускаем увеличение счетчика в конце блока match
-                }
-                //TODO: Нет четкого описания что делает данная команда.
-                // Вместо бесконечного цикла можно использовать NOP,
-                // заканчивать выполнение или возращать проц в дефолт
-                //KIL
-                0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2
-                | 0xF2 => {
-                    //Unofficial opcode, infinite loop
-                    loop {}
-                }
-                //LAS
-                0xBB => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address) & self.stack_pointer;
-
-                    self.accumulator = value;
-                    self.register_x = value;
-                    self.stack_pointer = value;
-
-                    self.update_negative_flag(value);
-                    self.update_zero_flag(value);
-                }
-                //LAX
-                0xA7 | 0xB7 | 0xAF | 0xBF | 0xA3 | 0xB3 | 0xAB => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.program_counter = address;
+                return cycles;
+            }
+            //KIL
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                //Unofficial opcode: процессор зависает до сброса
+                self.jammed = true;
+                self.program_counter = self.program_counter.wrapping_sub(1);
+                return cycles;
+            }
+            //LAS
+            0xBB => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address) & self.stack_pointer;
+
+                self.accumulator = value;
+                self.register_x = value;
+                self.stack_pointer = value;
+
+                self.update_negative_flag(value);
+                self.update_zero_flag(value);
+            }
+            //LAX
+            0xA7 | 0xB7 | 0xAF | 0xBF | 0xA3 | 0xB3 | 0xAB => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.accumulator = value;
-                    self.register_x = value;
+                self.accumulator = value;
+                self.register_x = value;
 
-                    self.update_negative_flag(value);
-                    self.update_zero_flag(value);
-                }
-                //LDA
-                0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.update_negative_flag(value);
+                self.update_zero_flag(value);
+            }
+            //LDA
+            0xA9 | 0xA5 | 0xB5 | 0xAD | 0xBD | 0xB9 | 0xA1 | 0xB1 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.set_register(Register::Accumulator, value);
-                }
-                //LDX
-                0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.set_register(Register::Accumulator, value);
+            }
+            //LDX
+            0xA2 | 0xA6 | 0xB6 | 0xAE | 0xBE => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.set_register(Register::X, value);
-                }
-                //LDY
-                0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.set_register(Register::X, value);
+            }
+            //LDY
+            0xA0 | 0xA4 | 0xB4 | 0xAC | 0xBC => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.set_register(Register::Y, value);
-                }
-                //LSR
-                0x4A | 0x46 | 0x56 | 0x4E | 0x5E => {
-                    let addressing_mode = instruction.addressing_mode;
-
-                    match addressing_mode {
-                        AddressingMode::Accumulator => {
-                            self.lsr_accum();
-                        }
-                        _ => {
-                            self.lsr_mem(addressing_mode);
-                        }
+                self.set_register(Register::Y, value);
+            }
+            //LSR
+            0x4A | 0x46 | 0x56 | 0x4E | 0x5E => {
+                let addressing_mode = instruction.addressing_mode;
+
+                match addressing_mode {
+                    AddressingMode::Accumulator => {
+                        self.lsr_accum();
+                    }
+                    _ => {
+                        self.lsr_mem(bus, addressing_mode);
                     }
                 }
-                //NOP
-                0xEA | 0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 | 0x04 | 0x44 | 0x64 | 0x14 | 0x34
-                | 0x54 | 0x74 | 0xD4 | 0xF4 | 0x0C | 0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC
-                | 0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => {}
-                //ORA
-                0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
-
-                    self.set_register(Register::Accumulator, self.accumulator | value);
-                }
-                //PHA
-                0x48 => {
-                    self.push_u8(self.accumulator);
-                }
-                //PHP
-                0x08 => {
-                    self.push_u8(self.status.bits);
-                }
-                //PLA
-                0x68 => {
-                    let stack_val = self.pop_u8();
-                    self.set_register(Register::Accumulator, stack_val);
-                }
-                //PLP
-                0x28 => {
-                    self.status.bits = self.pop_u8();
-                    self.status.insert(CpuFlags::ONE);
-                }
-                //RLA
-                0x27 | 0x37 | 0x2F | 0x3F | 0x3B | 0x33 | 0x23 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let value = self.rol_mem(addressing_mode);
+            }
+            //NOP
+            0xEA | 0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 | 0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54
+            | 0x74 | 0xD4 | 0xF4 | 0x0C | 0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => {}
+            //NOP с абсолютным индексным адресом читает операнд, как LDA
+            0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+
+                bus.read_u8(address);
+            }
+            //ORA
+            0x09 | 0x05 | 0x15 | 0x0D | 0x1D | 0x19 | 0x01 | 0x11 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.set_register(Register::Accumulator, self.accumulator & value);
-                }
-                //ROL
-                0x2A | 0x26 | 0x36 | 0x2E | 0x3E => {
-                    let addressing_mode = instruction.addressing_mode;
-
-                    match addressing_mode {
-                        AddressingMode::Accumulator => {
-                            self.rol_accum();
-                        }
-                        _ => {
-                            self.rol_mem(addressing_mode);
-                        }
+                self.set_register(Register::Accumulator, self.accumulator | value);
+            }
+            //PHA
+            0x48 => {
+                self.push_u8(bus, self.accumulator);
+            }
+            //PHP
+            0x08 => {
+                self.push_u8(bus, (self.status | CpuFlags::BREAK | CpuFlags::ONE).bits);
+            }
+            //PLA
+            0x68 => {
+                let stack_val = self.pop_u8(bus);
+                self.set_register(Register::Accumulator, stack_val);
+            }
+            //PLP
+            0x28 => {
+                self.pull_status(bus);
+            }
+            //RLA
+            0x27 | 0x37 | 0x2F | 0x3F | 0x3B | 0x33 | 0x23 => {
+                let addressing_mode = instruction.addressing_mode;
+                let value = self.rol_mem(bus, addressing_mode);
+
+                self.set_register(Register::Accumulator, self.accumulator & value);
+            }
+            //ROL
+            0x2A | 0x26 | 0x36 | 0x2E | 0x3E => {
+                let addressing_mode = instruction.addressing_mode;
+
+                match addressing_mode {
+                    AddressingMode::Accumulator => {
+                        self.rol_accum();
                     }
-                }
-                //ROR
-                0x6A | 0x66 | 0x76 | 0x6E | 0x7E => {
-                    let addressing_mode = instruction.addressing_mode;
-
-                    match addressing_mode {
-                        AddressingMode::Accumulator => {
-                            self.ror_accum();
-                        }
-                        _ => {
-                            self.ror_mem(addressing_mode);
-                        }
+                    _ => {
+                        self.rol_mem(bus, addressing_mode);
                     }
                 }
-                //RRA
-                0x67 | 0x77 | 0x6F | 0x7F | 0x7B | 0x63 | 0x73 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let value = self.ror_mem(addressing_mode);
+            }
+            //ROR
+            0x6A | 0x66 | 0x76 | 0x6E | 0x7E => {
+                let addressing_mode = instruction.addressing_mode;
 
-                    self.adc(value);
-                }
-                //RTI
-                0x40 => {
-                    self.status.bits = self.pop_u8();
-                    self.program_counter = self.pop_u16();
-                }
-                //RTS
-                0x60 => {
-                    self.program_counter = self.pop_u16();
-                    continue; //Пропускаем увеличение счетчика в конце блока match
+                match addressing_mode {
+                    AddressingMode::Accumulator => {
+                        self.ror_accum();
+                    }
+                    _ => {
+                        self.ror_mem(bus, addressing_mode);
+                    }
                 }
-                //SAX
-                0x87 | 0x97 | 0x8F | 0x83 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+            }
+            //RRA
+            0x67 | 0x77 | 0x6F | 0x7F | 0x7B | 0x63 | 0x73 => {
+                let addressing_mode = instruction.addressing_mode;
+                let value = self.ror_mem(bus, addressing_mode);
 
-                    self.write_u8(address, self.accumulator & self.register_x);
-                }
-                //SBC
-                0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD | 0xF9 | 0xE1 | 0xF1 | 0xEB => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                self.adc(value);
+            }
+            //RTI
+            0x40 => {
+                self.pull_status(bus);
+                self.program_counter = self.pop_u16(bus);
+                return cycles;
+            }
+            //RTS
+            0x60 => {
+                self.program_counter = self.pop_u16(bus).wrapping_add(1);
+                return cycles;
+            }
+            //SAX
+            0x87 | 0x97 | 0x8F | 0x83 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.sbc(value);
-                }
-                //SEC
-                0x38 => {
-                    self.set_carry_flag(true);
-                }
-                //SED
-                0xF8 => {
-                    self.set_decimal_mode_flag(true);
-                }
-                //SEI
-                0x78 => {
-                    self.set_interrupt_disable_flag(true);
-                }
-                //SHX
-                0x9E => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                bus.write_u8(address, self.accumulator & self.register_x);
+            }
+            //SBC
+            0xE9 | 0xE5 | 0xF5 | 0xED | 0xFD | 0xF9 | 0xE1 | 0xF1 | 0xEB => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-                    self.write_u8(address, self.register_x & address.to_be_bytes()[0]);
-                }
-                //SHY
-                0x9C => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                self.sbc(value);
+            }
+            //SEC
+            0x38 => {
+                self.set_carry_flag(true);
+            }
+            //SED
+            0xF8 => {
+                self.set_decimal_mode_flag(true);
+            }
+            //SEI
+            0x78 => {
+                self.set_interrupt_disable_flag(true);
+            }
+            //SHX
+            0x9E => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.write_u8(address, self.register_y & address.to_be_bytes()[0]);
-                }
-                //SLO
-                0x07 | 0x17 | 0x0F | 0x1F | 0x1B | 0x03 | 0x13 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let value = self.asl_mem(addressing_mode);
+                bus.write_u8(address, self.register_x & address.to_be_bytes()[0]);
+            }
+            //SHY
+            0x9C => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.set_register(Register::Accumulator, self.accumulator | value);
-                }
-                //SRE
-                0x47 | 0x57 | 0x4F | 0x5F | 0x5B | 0x43 | 0x53 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let value = self.lsr_mem(addressing_mode);
+                bus.write_u8(address, self.register_y & address.to_be_bytes()[0]);
+            }
+            //SLO
+            0x07 | 0x17 | 0x0F | 0x1F | 0x1B | 0x03 | 0x13 => {
+                let addressing_mode = instruction.addressing_mode;
+                let value = self.asl_mem(bus, addressing_mode);
 
-                    self.set_register(Register::Accumulator, self.accumulator ^ value);
-                }
-                //STA
-                0x85 | 0x95 | 0x8D | 0x9D | 0x99 | 0x81 | 0x91 => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                self.set_register(Register::Accumulator, self.accumulator | value);
+            }
+            //SRE
+            0x47 | 0x57 | 0x4F | 0x5F | 0x5B | 0x43 | 0x53 => {
+                let addressing_mode = instruction.addressing_mode;
+                let value = self.lsr_mem(bus, addressing_mode);
 
-                    self.write_u8(address, self.accumulator);
-                }
-                //STX
-                0x86 | 0x96 | 0x8E => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                self.set_register(Register::Accumulator, self.accumulator ^ value);
+            }
+            //STA
+            0x85 | 0x95 | 0x8D | 0x9D | 0x99 | 0x81 | 0x91 => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.write_u8(address, self.register_x);
-                }
-                //STY
-                0x84 | 0x94 | 0x8C => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                bus.write_u8(address, self.accumulator);
+            }
+            //STX
+            0x86 | 0x96 | 0x8E => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.write_u8(address, self.register_y);
-                }
-                //TAS
-                0x9B => {
-                    self.stack_pointer = self.accumulator & self.register_x;
+                bus.write_u8(address, self.register_x);
+            }
+            //STY
+            0x84 | 0x94 | 0x8C => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
+                bus.write_u8(address, self.register_y);
+            }
+            //TAS
+            0x9B => {
+                self.stack_pointer = self.accumulator & self.register_x;
 
-                    self.write_u8(address, self.register_y & address.to_be_bytes()[0]);
-                }
-                //TAX
-                0xAA => {
-                    self.set_register(Register::X, self.accumulator);
-                }
-                //TAY
-                0xA8 => {
-                    self.set_register(Register::Y, self.accumulator);
-                }
-                //TSX
-                0xBA => {
-                    self.set_register(Register::X, self.stack_pointer);
-                }
-                //TXA
-                0x8A => {
-                    self.set_register(Register::Accumulator, self.register_x);
-                }
-                //TXS
-                0x9A => {
-                    self.set_register(Register::Stack, self.register_x);
-                }
-                //TYA
-                0x98 => {
-                    self.set_register(Register::Accumulator, self.register_y);
-                }
-                //XAA
-                0x8B => {
-                    let addressing_mode = instruction.addressing_mode;
-                    let address = self.address(addressing_mode);
-                    let value = self.read_u8(address);
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
 
-                    self.set_register(Register::Accumulator, self.register_x & value);
-                }
-                _ => unimplemented!("That opcode unimplemented"),
+                bus.write_u8(address, self.register_y & address.to_be_bytes()[0]);
+            }
+            //TAX
+            0xAA => {
+                self.set_register(Register::X, self.accumulator);
+            }
+            //TAY
+            0xA8 => {
+                self.set_register(Register::Y, self.accumulator);
+            }
+            //TSX
+            0xBA => {
+                self.set_register(Register::X, self.stack_pointer);
             }
+            //TXA
+            0x8A => {
+                self.set_register(Register::Accumulator, self.register_x);
+            }
+            //TXS
+            0x9A => {
+                self.set_register(Register::Stack, self.register_x);
+            }
+            //TYA
+            0x98 => {
+                self.set_register(Register::Accumulator, self.register_y);
+            }
+            //XAA
+            0x8B => {
+                let addressing_mode = instruction.addressing_mode;
+                let address = self.address(bus, addressing_mode);
+                let value = bus.read_u8(address);
 
-            self.inc_program_counter(instruction.len as u16 - 1);
+                self.set_register(Register::Accumulator, self.register_x & value);
+            }
+            _ => unimplemented!("That opcode unimplemented"),
         }
+
+        self.inc_program_counter(instruction.len as u16 - 1);
+
+        //Чтение через границу страницы занимает лишний такт
+        if self.page_crossed && page_cross_penalty {
+            cycles += 1;
+        }
+
+        cycles
+    }
+
+    //Вход в прерывание занимает INTERRUPT_CYCLES тактов
+    pub fn nmi(&mut self, bus: &mut dyn Memory) {
+        self.interrupt(bus, NMI_RESET);
+    }
+
+    //false - IRQ замаскирован флагом I
+    pub fn irq(&mut self, bus: &mut dyn Memory) -> bool {
+        if self.status.contains(CpuFlags::INTERRUPT_DISABLE) {
+            return false;
+        }
+
+        self.interrupt(bus, IRQ_INT);
+        true
     }
 
-    //TODO: Подумать над введением 2 аргумента,
-    // который можно будет использвоать вместо PC
-    // Написать тесты, скорее всего часть сделано неправильно
-    fn address(&self, mode: AddressingMode) -> u16 {
+    fn address(&mut self, bus: &mut dyn Memory, mode: AddressingMode) -> u16 {
         match mode {
             AddressingMode::Immediate | AddressingMode::Relative => self.program_counter,
 
-            AddressingMode::ZeroPage => self.read_u8(self.program_counter) as u16,
+            AddressingMode::ZeroPage => bus.read_u8(self.program_counter) as u16,
 
+            //Индекс не выводит адрес за нулевую страницу
             AddressingMode::ZeroPageX => {
-                let base = self.read_u8(self.program_counter) as u16;
-                base.wrapping_add(self.register_x as u16)
+                let base = bus.read_u8(self.program_counter);
+                base.wrapping_add(self.register_x) as u16
             }
 
             AddressingMode::ZeroPageY => {
-                let base = self.read_u8(self.program_counter) as u16;
-                base.wrapping_add(self.register_y as u16)
+                let base = bus.read_u8(self.program_counter);
+                base.wrapping_add(self.register_y) as u16
             }
 
-            AddressingMode::Absolute => self.read_u16(self.program_counter),
+            AddressingMode::Absolute => bus.read_u16(self.program_counter),
 
             AddressingMode::AbsoluteX => {
-                let base = self.read_u16(self.program_counter);
-                base.wrapping_add(self.register_x as u16)
+                let base = bus.read_u16(self.program_counter);
+                self.indexed(base, self.register_x)
             }
 
             AddressingMode::AbsoluteY => {
-                let base = self.read_u16(self.program_counter);
-                base.wrapping_add(self.register_y as u16)
+                let base = bus.read_u16(self.program_counter);
+                self.indexed(base, self.register_y)
             }
 
+            //Ошибка 6502: старший байт указателя $xxFF читается из $xx00
             AddressingMode::Indirect => {
-                let address = self.read_u16(self.program_counter);
-                self.read_u16(address)
+                let address = bus.read_u16(self.program_counter);
+                let lo = bus.read_u8(address);
+                let hi = bus.read_u8((address & 0xFF00) | (address.wrapping_add(1) & 0x00FF));
+
+                u16::from_le_bytes([lo, hi])
             }
 
             AddressingMode::IndirectX => {
-                let address = self.read_u16(self.program_counter);
-                self.read_u16(address.wrapping_add(self.register_x as u16))
+                let base = bus.read_u8(self.program_counter);
+                self.read_zero_page_u16(bus, base.wrapping_add(self.register_x))
             }
 
             AddressingMode::IndirectY => {
-                let address = self.read_u16(self.program_counter);
-                self.read_u16(address.wrapping_add(self.register_y as u16))
+                let base = bus.read_u8(self.program_counter);
+                let pointer = self.read_zero_page_u16(bus, base);
+                self.indexed(pointer, self.register_y)
             }
 
             AddressingMode::Implied | AddressingMode::Accumulator => unreachable!(),
         }
     }
 
-    fn interrupt(&mut self, interrupt: Interrupt) {
-        let interrupt_disable = self.status.contains(CpuFlags::INTERRUPT_DISABLE);
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let address = base.wrapping_add(index as u16);
+        self.page_crossed = base & 0xFF00 != address & 0xFF00;
 
-        if interrupt_disable
-            && ((interrupt.int_type == InterruptType::IRQ)
-                || (interrupt.int_type == InterruptType::BRK))
-        {
-            return;
-        }
+        address
+    }
 
-        match interrupt.int_type {
-            InterruptType::Reset => {}
-            _ => {
-                self.push_u16(self.program_counter);
-                self.push_u8(self.status.bits);
-            }
-        }
+    //Указатель в нулевой странице: старший байт после $FF читается из $00
+    fn read_zero_page_u16(&self, bus: &mut dyn Memory, address: u8) -> u16 {
+        let lo = bus.read_u8(address as u16);
+        let hi = bus.read_u8(address.wrapping_add(1) as u16);
+
+        u16::from_le_bytes([lo, hi])
+    }
+
+    //Флаг B существует только в копии статуса в стеке: 1 у BRK и PHP, 0 у NMI и IRQ
+    fn interrupt(&mut self, bus: &mut dyn Memory, interrupt: Interrupt) {
+        let mut status = self.status | CpuFlags::ONE;
+        status.set(CpuFlags::BREAK, interrupt.int_type == InterruptType::BRK);
+
+        self.push_u16(bus, self.program_counter);
+        self.push_u8(bus, status.bits);
 
         self.set_interrupt_disable_flag(true);
-        self.program_counter = self.read_u16(interrupt.vec_addr);
+        self.program_counter = bus.read_u16(interrupt.vec_addr);
+    }
+
+    fn pull_status(&mut self, bus: &mut dyn Memory) {
+        self.status.bits = self.pop_u8(bus);
+        self.status.remove(CpuFlags::BREAK);
+        self.status.insert(CpuFlags::ONE);
     }
 
     //TODO: Заменить инкремент счеткчика данной функцией,
@@ -695,10 +768,6 @@ impl CPU {
         self.status.set(CpuFlags::DECIMAL_MODE, value);
     }
 
-    fn set_break_command_flag(&mut self, value: bool) {
-        self.status.set(CpuFlags::BREAK, value);
-    }
-
     fn set_overflow_flag(&mut self, value: bool) {
         self.status.set(CpuFlags::OVERFLOW, value);
     }
@@ -727,10 +796,12 @@ impl CPU {
         };
 
         let sum = self.accumulator as u16 + value as u16 + carry;
+        let result = sum as u8;
         self.set_carry_flag(sum & CARRY_MASK != 0);
-        self.set_overflow_flag(sum & OVERFLOW_MASK != 0);
+        //Переполнение: у слагаемых один знак, у результата другой
+        self.set_overflow_flag((self.accumulator ^ result) & (value ^ result) & 0b1000_0000 != 0);
 
-        self.set_register(Register::Accumulator, sum as u8);
+        self.set_register(Register::Accumulator, result);
     }
 
     fn asl_accum(&mut self) {
@@ -738,9 +809,9 @@ impl CPU {
         self.set_register(Register::Accumulator, self.accumulator << 1);
     }
 
-    fn asl_mem(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let address = self.address(addressing_mode);
-        let mut value = self.read_u8(address);
+    fn asl_mem(&mut self, bus: &mut dyn Memory, addressing_mode: AddressingMode) -> u8 {
+        let address = self.address(bus, addressing_mode);
+        let mut value = bus.read_u8(address);
 
         self.set_carry_flag((value & 0b1000_0000) != 0);
 
@@ -748,7 +819,7 @@ impl CPU {
         self.update_zero_flag(value);
         self.update_negative_flag(value);
 
-        self.write_u8(address, value);
+        bus.write_u8(address, value);
 
         value
     }
@@ -759,9 +830,16 @@ impl CPU {
         self.update_negative_flag(value);
     }
 
-    fn branch(&mut self) {
-        let offset = self.read_u8(self.program_counter) as i8;
+    //Переход занимает лишний такт и еще один при смене страницы
+    fn branch(&mut self, bus: &mut dyn Memory) -> u8 {
+        let offset = bus.read_u8(self.program_counter) as i8;
+        let next = self.program_counter.wrapping_add(1);
         self.inc_program_counter(offset as u16);
+
+        match next & 0xFF00 == self.program_counter.wrapping_add(1) & 0xFF00 {
+            true => 1,
+            false => 2,
+        }
     }
 
     fn compare(&mut self, lhs: u8, rhs: u8) {
@@ -793,9 +871,9 @@ impl CPU {
         self.set_register(Register::Accumulator, self.accumulator >> 1);
     }
 
-    fn lsr_mem(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let address = self.address(addressing_mode);
-        let mut value = self.read_u8(address);
+    fn lsr_mem(&mut self, bus: &mut dyn Memory, addressing_mode: AddressingMode) -> u8 {
+        let address = self.address(bus, addressing_mode);
+        let mut value = bus.read_u8(address);
 
         self.set_carry_flag((value & 0b0000_0001) != 0);
 
@@ -803,7 +881,7 @@ impl CPU {
         self.update_zero_flag(value);
         self.update_negative_flag(value);
 
-        self.write_u8(address, value);
+        bus.write_u8(address, value);
 
         value
     }
@@ -820,21 +898,22 @@ impl CPU {
         self.set_register(Register::Accumulator, value);
     }
 
-    fn rol_mem(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let address = self.address(addressing_mode);
-        let mut value = self.read_u8(address);
+    fn rol_mem(&mut self, bus: &mut dyn Memory, addressing_mode: AddressingMode) -> u8 {
+        let address = self.address(bus, addressing_mode);
+        let mut value = bus.read_u8(address);
 
         let new_carry = (value & 0b1000_0000) != 0;
-        value <<= 1 | match self.status.contains(CpuFlags::CARRY) {
-            true => 0b0000_0001,
-            false => 0b0000_0000,
-        };
+        value = value << 1
+            | match self.status.contains(CpuFlags::CARRY) {
+                true => 0b0000_0001,
+                false => 0b0000_0000,
+            };
 
         self.set_carry_flag(new_carry);
         self.update_zero_flag(value);
         self.update_negative_flag(value);
 
-        self.write_u8(address, value);
+        bus.write_u8(address, value);
 
         value
     }
@@ -851,81 +930,61 @@ impl CPU {
         self.set_register(Register::Accumulator, value);
     }
 
-    fn ror_mem(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let address = self.address(addressing_mode);
-        let mut value = self.read_u8(address);
+    fn ror_mem(&mut self, bus: &mut dyn Memory, addressing_mode: AddressingMode) -> u8 {
+        let address = self.address(bus, addressing_mode);
+        let mut value = bus.read_u8(address);
 
         let new_carry = (value & 0b0000_0001) != 0;
-        value >>= 1 | match self.status.contains(CpuFlags::CARRY) {
-            true => 0b1000_0000,
-            false => 0b0000_0000,
-        };
+        value = value >> 1
+            | match self.status.contains(CpuFlags::CARRY) {
+                true => 0b1000_0000,
+                false => 0b0000_0000,
+            };
 
         self.set_carry_flag(new_carry);
         self.update_zero_flag(value);
         self.update_negative_flag(value);
 
-        self.write_u8(address, value);
+        bus.write_u8(address, value);
 
         value
     }
 
+    //A - M - (1 - C) = A + !M + C: перенос означает отсутствие заема
     fn sbc(&mut self, value: u8) {
-        let carry = match self.status.contains(CpuFlags::CARRY) {
-            true => 0,
-            false => 1,
-        };
-
-        let div = (self.accumulator as u16).wrapping_sub(value as u16 + carry);
-        self.set_carry_flag(div & CARRY_MASK != 0);
-        self.set_overflow_flag(div & OVERFLOW_MASK != 0);
-
-        self.set_register(Register::Accumulator, div as u8);
+        self.adc(!value);
     }
 }
 
-impl Memory for CPU {
-    fn read_u8(&self, address: u16) -> u8 {
-        self.memory[address as usize]
-    }
-
-    fn read_u16(&self, address: u16) -> u16 {
-        let lo = self.memory[address as usize];
-        let hi = self.memory[address.wrapping_add(1) as usize];
-
-        u16::from_le_bytes([lo, hi])
-    }
-
-    fn write_u8(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
-    }
-
-    fn write_u16(&mut self, address: u16, value: u16) {
-        let bytes = value.to_le_bytes();
-        self.memory[address as usize] = bytes[0];
-        self.memory[address.wrapping_add(1) as usize] = bytes[1];
+impl Default for CPU {
+    fn default() -> Self {
+        CPU::new()
     }
 }
 
+//Младший байт лежит ниже: старший кладется в стек первым
 impl Stack for CPU {
-    fn pop_u8(&mut self) -> u8 {
+    fn pop_u8(&mut self, bus: &mut dyn Memory) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        self.read_u8(STACK_ADDRESS + self.stack_pointer as u16)
+        bus.read_u8(STACK_ADDRESS + self.stack_pointer as u16)
     }
 
-    fn pop_u16(&mut self) -> u16 {
-        self.stack_pointer = self.stack_pointer.wrapping_add(2);
-        self.read_u16(STACK_ADDRESS + self.stack_pointer as u16)
+    fn pop_u16(&mut self, bus: &mut dyn Memory) -> u16 {
+        let lo = self.pop_u8(bus);
+        let hi = self.pop_u8(bus);
+
+        u16::from_le_bytes([lo, hi])
     }
 
-    fn push_u8(&mut self, value: u8) {
-        self.write_u8(STACK_ADDRESS + self.stack_pointer as u16, value);
+    fn push_u8(&mut self, bus: &mut dyn Memory, value: u8) {
+        bus.write_u8(STACK_ADDRESS + self.stack_pointer as u16, value);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
-    fn push_u16(&mut self, value: u16) {
-        self.write_u16(STACK_ADDRESS + self.stack_pointer as u16, value);
-        self.stack_pointer = self.stack_pointer.wrapping_sub(2);
+    fn push_u16(&mut self, bus: &mut dyn Memory, value: u16) {
+        let bytes = value.to_le_bytes();
+        self.push_u8(bus, bytes[1]);
+        self.push_u8(bus, bytes[0]);
     }
 }
 
@@ -985,6 +1044,16 @@ mod cpu_test {
         let mut cpu = CPU::new();
         cpu.execute_commands(vec![0xA9, 255, 0x69, 129]);
 
+        //-1 + -127 = -128 помещается в байт со знаком
+        assert_eq!(cpu.accumulator, 128);
+        assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_adc_signed_overflow() {
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA9, 127, 0x69, 1]);
+
         assert_eq!(cpu.accumulator, 128);
         assert!(cpu.status.contains(CpuFlags::OVERFLOW));
     }
@@ -998,14 +1067,331 @@ mod cpu_test {
         assert!(cpu.status.contains(CpuFlags::CARRY));
     }
 
+    #[test]
+    fn test_sbc_carry() {
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0x38, 0xA9, 5, 0xE9, 3]);
+
+        assert_eq!(cpu.accumulator, 2);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+
+        //Заем сбрасывает перенос
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0x38, 0xA9, 3, 0xE9, 5]);
+
+        assert_eq!(cpu.accumulator, 0xFE);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_sbc_overflow() {
+        //-128 - 1 не помещается в байт со знаком
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0x38, 0xA9, 0x80, 0xE9, 1]);
+
+        assert_eq!(cpu.accumulator, 0x7F);
+        assert!(cpu.status.contains(CpuFlags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_axs() {
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA9, 0x0F, 0xA2, 0xFF, 0xCB, 0x05]);
+
+        assert_eq!(cpu.register_x, 0x0A);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA9, 0x0F, 0xA2, 0xFF, 0xCB, 0x10]);
+
+        assert_eq!(cpu.register_x, 0xFF);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_rol_ror_memory() {
+        //LDA #$81; STA $10; SEC; ROL $10; LDX $10
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA9, 0x81, 0x85, 0x10, 0x38, 0x26, 0x10, 0xA6, 0x10]);
+
+        assert_eq!(cpu.register_x, 0x03);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+
+        //LDA #$81; STA $10; SEC; ROR $10; LDX $10
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA9, 0x81, 0x85, 0x10, 0x38, 0x66, 0x10, 0xA6, 0x10]);
+
+        assert_eq!(cpu.register_x, 0xC0);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_zero_page_index_wrap() {
+        //LDX #$10; LDA #$42; STA $F8,X; LDY $08
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA2, 0x10, 0xA9, 0x42, 0x95, 0xF8, 0xA4, 0x08]);
+
+        assert_eq!(cpu.register_y, 0x42);
+
+        //LDY #$10; LDA #$42; STA $08; LDX $F8,Y
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA0, 0x10, 0xA9, 0x42, 0x85, 0x08, 0xB6, 0xF8]);
+
+        assert_eq!(cpu.register_x, 0x42);
+    }
+
+    #[test]
+    fn test_indexed_indirect_addressing_mode() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        cpu.program_counter = 0x40;
+        cpu.register_x = 0x04;
+        cpu.register_y = 0x10;
+        memory[0x40] = 0xFE;
+        //($FE,X) -> $02, указатель $02/$03. ($FE),Y -> $FE/$FF
+        memory[0x02] = 0x34;
+        memory[0x03] = 0x12;
+        memory[0xFE] = 0x00;
+        memory[0xFF] = 0x20;
+
+        assert_eq!(cpu.address(&mut memory, AddressingMode::IndirectX), 0x1234);
+        assert_eq!(cpu.address(&mut memory, AddressingMode::IndirectY), 0x2010);
+
+        //Указатель в $FF: старший байт из $00
+        cpu.register_x = 0x01;
+        memory[0x00] = 0x56;
+        memory[0xFF] = 0x78;
+        assert_eq!(cpu.address(&mut memory, AddressingMode::IndirectX), 0x5678);
+    }
+
+    #[test]
+    fn test_indirect_page_wrap() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        cpu.program_counter = 0x40;
+        //Старший байт указателя ($10FF) берется из $1000
+        memory[0x40] = 0xFF;
+        memory[0x41] = 0x10;
+        memory[0x10FF] = 0x78;
+        memory[0x1000] = 0x56;
+        memory[0x1100] = 0x9A;
+
+        assert_eq!(cpu.address(&mut memory, AddressingMode::Indirect), 0x5678);
+    }
+
+    #[test]
+    fn test_jmp() {
+        //JMP $0006; LDA #1; NOP; LDX #2
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0x4C, 0x06, 0x00, 0xA9, 0x01, 0xEA, 0xA2, 0x02]);
+
+        assert_eq!(cpu.accumulator, 0);
+        assert_eq!(cpu.register_x, 2);
+    }
+
+    #[test]
+    fn test_stack_u16() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        cpu.stack_pointer = 0xFD;
+        cpu.push_u16(&mut memory, 0x1234);
+
+        assert_eq!(cpu.stack_pointer, 0xFB);
+        assert_eq!(&memory[0x1FC..0x1FE], &[0x34, 0x12]);
+        assert_eq!(cpu.pop_u16(&mut memory), 0x1234);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+
+        //Указатель стека переходит с $0100 на $01FF внутри страницы
+        cpu.stack_pointer = 0x00;
+        cpu.push_u16(&mut memory, 0xABCD);
+        assert_eq!(memory[0x100], 0xAB);
+        assert_eq!(memory[0x1FF], 0xCD);
+        assert_eq!(cpu.pop_u16(&mut memory), 0xABCD);
+    }
+
+    #[test]
+    fn test_jsr_rts() {
+        //$00: JSR $0008; $03: LDY #5; $05: JMP $000C; $08: LDX #1; $0A: RTS
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.execute_commands(vec![
+            0x20, 0x08, 0x00, 0xA0, 0x05, 0x4C, 0x0C, 0x00, 0xA2, 0x01, 0x60, 0xEA,
+        ]);
+
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.register_y, 5);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+
+        //В стеке адрес последнего байта JSR: PLA; TAX; PLA; TAY
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.execute_commands(vec![0x20, 0x03, 0x00, 0x68, 0xAA, 0x68, 0xA8]);
+
+        assert_eq!(cpu.register_x, 0x02);
+        assert_eq!(cpu.register_y, 0x00);
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        cpu.stack_pointer = 0xFD;
+        memory[0xFFFE..].copy_from_slice(&[0x06, 0x00]);
+        //SEI; BRK; $FF; LDA #1; NOP; $06: LDX #2
+        memory[..8].copy_from_slice(&[0x78, 0x00, 0xFF, 0xA9, 0x01, 0xEA, 0xA2, 0x02]);
+
+        for _ in 0..3 {
+            cpu.step(&mut memory);
+        }
+
+        //BRK не маскируется и пропускает байт после себя
+        assert_eq!(cpu.accumulator, 0);
+        assert_eq!(cpu.register_x, 2);
+        assert_eq!(&memory[0x1FC..0x1FE], &[0x03, 0x00]);
+
+        //B есть только в копии статуса в стеке
+        assert_ne!(memory[0x1FB] & CpuFlags::BREAK.bits, 0);
+        assert!(!cpu.status.contains(CpuFlags::BREAK));
+    }
+
+    #[test]
+    fn test_status_push_pull() {
+        //PHP; PLA: B и бит 5 выставлены в копии статуса
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.execute_commands(vec![0x08, 0x68]);
+
+        assert_eq!(cpu.accumulator, 0x30);
+
+        //LDA #$FF; PHA; PLP: B из стека не попадает в регистр
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.execute_commands(vec![0xA9, 0xFF, 0x48, 0x28]);
+
+        assert_eq!(cpu.status.bits, 0xEF);
+
+        //RTI снимает статус и адрес $000A: LDA #0; PHA; LDA #$0A; PHA; LDA #$FF; PHA; RTI
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xFD;
+        cpu.execute_commands(vec![
+            0xA9, 0x00, 0x48, 0xA9, 0x0A, 0x48, 0xA9, 0xFF, 0x48, 0x40, 0xA2, 0x01,
+        ]);
+
+        assert_eq!(cpu.register_x, 1);
+        assert!(!cpu.status.contains(CpuFlags::BREAK));
+        assert!(cpu
+            .status
+            .contains(CpuFlags::DECIMAL_MODE | CpuFlags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        //$10F0: BNE +2; BEQ +$10
+        memory[0x10F0..0x10F4].copy_from_slice(&[0xD0, 0x02, 0xF0, 0x10]);
+        cpu.program_counter = 0x10F0;
+
+        //Переход не выполнен
+        cpu.status.insert(CpuFlags::ZERO);
+        assert_eq!(cpu.step(&mut memory), 2);
+        assert_eq!(cpu.program_counter, 0x10F2);
+
+        //Переход в пределах страницы
+        cpu.program_counter = 0x10F0;
+        cpu.status.remove(CpuFlags::ZERO);
+        assert_eq!(cpu.step(&mut memory), 3);
+        assert_eq!(cpu.program_counter, 0x10F4);
+
+        //Переход на следующую страницу
+        cpu.program_counter = 0x10F2;
+        cpu.status.insert(CpuFlags::ZERO);
+        assert_eq!(cpu.step(&mut memory), 4);
+        assert_eq!(cpu.program_counter, 0x1104);
+    }
+
+    #[test]
+    fn test_page_cross_cycles() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        //LDA $10FF,X; STA $10FF,X
+        memory[..6].copy_from_slice(&[0xBD, 0xFF, 0x10, 0x9D, 0xFF, 0x10]);
+        memory[0x1100] = 0x42;
+
+        //Чтение без пересечения страницы
+        assert_eq!(cpu.step(&mut memory), 4);
+        assert_eq!(cpu.accumulator, 0);
+
+        //Чтение из $1100 занимает лишний такт
+        cpu.program_counter = 0;
+        cpu.register_x = 1;
+        assert_eq!(cpu.step(&mut memory), 5);
+        assert_eq!(cpu.accumulator, 0x42);
+
+        //Запись всегда занимает 5 тактов
+        assert_eq!(cpu.step(&mut memory), 5);
+        cpu.program_counter = 3;
+        cpu.register_x = 0;
+        assert_eq!(cpu.step(&mut memory), 5);
+
+        //($10),Y: указатель $10FF, чтение из $1100
+        memory[0x10..0x12].copy_from_slice(&[0xFF, 0x10]);
+        memory[0x20..0x22].copy_from_slice(&[0xB1, 0x10]);
+        cpu.program_counter = 0x20;
+        cpu.register_y = 1;
+        assert_eq!(cpu.step(&mut memory), 6);
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
+        memory[0xFFFA..].copy_from_slice(&[0x00, 0x80, 0x00, 0xC0, 0x00, 0x90]);
+        memory[0x8000] = 0x40;
+
+        cpu.reset(&mut memory);
+        assert_eq!(cpu.program_counter, 0xC000);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+
+        //IRQ замаскирован после сброса
+        assert!(!cpu.irq(&mut memory));
+
+        cpu.nmi(&mut memory);
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(memory[0x1FB] & CpuFlags::BREAK.bits, 0);
+
+        //RTI
+        cpu.step(&mut memory);
+        assert_eq!(cpu.program_counter, 0xC000);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+
+        //BRK пропускает байт после себя и кладет в стек флаг B
+        memory[0xC000] = 0x00;
+        cpu.step(&mut memory);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(&memory[0x1FC..0x1FE], &[0x02, 0xC0]);
+        assert_ne!(memory[0x1FB] & CpuFlags::BREAK.bits, 0);
+    }
+
+    #[test]
+    fn test_kil() {
+        let mut cpu = CPU::new();
+        cpu.execute_commands(vec![0xA9, 1, 0x02, 0xA9, 2]);
+
+        assert!(cpu.jammed);
+        assert_eq!(cpu.accumulator, 1);
+        assert_eq!(cpu.program_counter, 2);
+    }
+
     #[test]
     fn test_zero_addressing_mode() {
         let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
         cpu.program_counter = 2;
-        cpu.memory[2] = 0x15;
-        cpu.memory[21] = 10;
-        let address = cpu.address(AddressingMode::ZeroPage);
-        let value = cpu.read_u8(address);
+        memory[2] = 0x15;
+        memory[21] = 10;
+        let address = cpu.address(&mut memory, AddressingMode::ZeroPage);
+        let value = memory.read_u8(address);
 
         assert_eq!(value, 10);
     }
@@ -1013,12 +1399,13 @@ mod cpu_test {
     #[test]
     fn test_absolute_addressing_mode() {
         let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
         cpu.program_counter = 2;
-        cpu.memory[2] = 0x15;
-        cpu.memory[3] = 0x10;
-        cpu.memory[0x1015] = 10;
-        let address = cpu.address(AddressingMode::Absolute);
-        let value = cpu.read_u8(address);
+        memory[2] = 0x15;
+        memory[3] = 0x10;
+        memory[0x1015] = 10;
+        let address = cpu.address(&mut memory, AddressingMode::Absolute);
+        let value = memory.read_u8(address);
 
         assert_eq!(value, 10);
     }
@@ -1026,12 +1413,13 @@ mod cpu_test {
     #[test]
     fn test_indirect_addressing_mode() {
         let mut cpu = CPU::new();
+        let mut memory = vec![0; 0x10000];
         cpu.program_counter = 2;
-        cpu.memory[2] = 0x15;
-        cpu.memory[3] = 0x10;
-        cpu.memory[0x1015] = 10;
-        cpu.memory[0x1016] = 20;
-        let ptr = cpu.address(AddressingMode::Indirect);
+        memory[2] = 0x15;
+        memory[3] = 0x10;
+        memory[0x1015] = 10;
+        memory[0x1016] = 20;
+        let ptr = cpu.address(&mut memory, AddressingMode::Indirect);
 
         assert_eq!(ptr, 5130);
     }
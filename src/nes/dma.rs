@@ -0,0 +1,215 @@
+const OAM_DMA_LENGTH: u16 = 256;
+
+//Что DMA делает с шиной в текущем такте CPU
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DmaCycle {
+    //DMA не активен, такт принадлежит CPU
+    Idle,
+    //CPU остановлен, шина простаивает (остановка, выравнивание, холостой такт DMC)
+    Wait,
+    //Остановка только ради DMC: CPU повторяет прерванное чтение
+    //(приближенно, см. NES::repeat_read)
+    Halt,
+    OamRead(u16),
+    OamWrite(u8),
+    DmcRead(u16),
+}
+
+//Контроллер OAM DMA ($4014) и DMA канала DMC. Чтение идет в четных тактах (get),
+//запись - в нечетных (put), поэтому OAM DMA занимает 513 или 514 тактов.
+//DMC забирает такт чтения у OAM DMA и обходится ему в два такта
+pub struct Dma {
+    halted: bool,
+
+    oam_active: bool,
+    oam_page: u8,
+    oam_index: u16,
+    oam_latch: Option<u8>,
+
+    dmc_address: Option<u16>,
+    dmc_ready: bool,
+    dmc_sample: Option<u8>,
+}
+
+#[allow(dead_code)]
+impl Dma {
+    pub fn new() -> Self {
+        Dma {
+            halted: false,
+
+            oam_active: false,
+            oam_page: 0,
+            oam_index: 0,
+            oam_latch: None,
+
+            dmc_address: None,
+            dmc_ready: false,
+            dmc_sample: None,
+        }
+    }
+
+    pub fn start_oam(&mut self, page: u8) {
+        self.oam_active = true;
+        self.oam_page = page;
+        self.oam_index = 0;
+        self.oam_latch = None;
+    }
+
+    pub fn request_dmc(&mut self, address: u16) {
+        self.dmc_address = Some(address);
+        self.dmc_ready = false;
+    }
+
+    pub fn active(&self) -> bool {
+        self.oam_active || self.dmc_address.is_some()
+    }
+
+    //Байт, прочитанный для DMC
+    pub fn take_dmc_sample(&mut self) -> Option<u8> {
+        self.dmc_sample.take()
+    }
+
+    pub fn complete_oam_read(&mut self, value: u8) {
+        self.oam_latch = Some(value);
+    }
+
+    pub fn complete_dmc_read(&mut self, value: u8) {
+        self.dmc_sample = Some(value);
+    }
+
+    pub fn cycle(&mut self, get: bool) -> DmaCycle {
+        if !self.active() {
+            self.halted = false;
+            return DmaCycle::Idle;
+        }
+
+        //Первый такт CPU только останавливается
+        if !self.halted {
+            self.halted = true;
+            return self.stall();
+        }
+
+        match get {
+            true => match (self.dmc_address, self.dmc_ready) {
+                (Some(address), true) => {
+                    self.dmc_address = None;
+                    DmaCycle::DmcRead(address)
+                }
+                _ if self.oam_active && self.oam_latch.is_none() => {
+                    DmaCycle::OamRead((self.oam_page as u16) << 8 | self.oam_index)
+                }
+                _ => self.stall(),
+            },
+            false => {
+                //Холостой такт DMC совпадает с любым тактом записи
+                self.dmc_ready = self.dmc_address.is_some();
+
+                match self.oam_latch.take() {
+                    Some(value) => {
+                        self.oam_index += 1;
+                        self.oam_active = self.oam_index < OAM_DMA_LENGTH;
+                        DmaCycle::OamWrite(value)
+                    }
+                    None => self.stall(),
+                }
+            }
+        }
+    }
+
+    //Без OAM DMA шину в тактах ожидания держит остановленный CPU
+    fn stall(&self) -> DmaCycle {
+        match self.oam_active {
+            true => DmaCycle::Wait,
+            false => DmaCycle::Halt,
+        }
+    }
+}
+
+impl Default for Dma {
+    fn default() -> Self {
+        Dma::new()
+    }
+}
+
+#[cfg(test)]
+mod dma_test {
+    use super::*;
+
+    //Прогоняет DMA до конца и возвращает число тактов остановки CPU
+    fn run(dma: &mut Dma, start_cycle: u64, dmc_at: Option<u64>) -> u64 {
+        let mut cycle = start_cycle;
+        loop {
+            if dmc_at == Some(cycle - start_cycle) {
+                dma.request_dmc(0xC000);
+            }
+
+            match dma.cycle(cycle & 1 == 0) {
+                DmaCycle::Idle => return cycle - start_cycle,
+                DmaCycle::OamRead(address) => dma.complete_oam_read(address as u8),
+                DmaCycle::DmcRead(_) => dma.complete_dmc_read(0x55),
+                _ => {}
+            }
+            cycle += 1;
+        }
+    }
+
+    #[test]
+    fn test_oam_dma_alignment() {
+        let mut dma = Dma::new();
+        dma.start_oam(2);
+        assert_eq!(run(&mut dma, 1, None), 513);
+
+        dma.start_oam(2);
+        assert_eq!(run(&mut dma, 0, None), 514);
+    }
+
+    #[test]
+    fn test_oam_dma_order() {
+        let mut dma = Dma::new();
+        dma.start_oam(3);
+
+        let mut cycles = vec![];
+        for cycle in 1..6 {
+            let step = dma.cycle(cycle & 1 == 0);
+            if let DmaCycle::OamRead(address) = step {
+                dma.complete_oam_read(address as u8 + 1);
+            }
+            cycles.push(step);
+        }
+
+        assert_eq!(
+            cycles,
+            [
+                DmaCycle::Wait,
+                DmaCycle::OamRead(0x0300),
+                DmaCycle::OamWrite(0x01),
+                DmaCycle::OamRead(0x0301),
+                DmaCycle::OamWrite(0x02),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dmc_dma() {
+        let mut dma = Dma::new();
+        dma.request_dmc(0xC000);
+        assert_eq!(run(&mut dma, 0, None), 3);
+        assert_eq!(dma.take_dmc_sample(), Some(0x55));
+
+        dma.request_dmc(0xC000);
+        assert_eq!(run(&mut dma, 1, None), 4);
+
+        dma.request_dmc(0xC000);
+        assert_eq!(dma.cycle(true), DmaCycle::Halt);
+        assert_eq!(dma.cycle(false), DmaCycle::Halt);
+        assert_eq!(dma.cycle(true), DmaCycle::DmcRead(0xC000));
+    }
+
+    #[test]
+    fn test_dmc_during_oam_dma() {
+        let mut dma = Dma::new();
+        dma.start_oam(2);
+        assert_eq!(run(&mut dma, 1, Some(100)), 515);
+        assert_eq!(dma.take_dmc_sample(), Some(0x55));
+    }
+}
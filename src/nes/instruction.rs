@@ -190,7 +190,7 @@ impl Instruction {
             0xF2 => Instruction::new(code, 1, 2, AddressingMode::Implied),
 
             //LAS
-            0xBB => Instruction::new(code, 3, 2, AddressingMode::AbsoluteY),
+            0xBB => Instruction::new(code, 3, 4, AddressingMode::AbsoluteY),
 
             //LAX - LDA + LDX
             0xA7 => Instruction::new(code, 2, 3, AddressingMode::ZeroPage),
@@ -414,4 +414,19 @@ impl Instruction {
             _ => unimplemented!("That code unimplemented"),
         }
     }
+
+    //Чтение с индексом (abs,X, abs,Y и (zp),Y), которое на другой странице
+    //тратит такт на исправление старшего байта адреса.
+    //Запись и чтение-модификация-запись этот такт тратят всегда
+    pub fn page_cross_penalty(&self) -> bool {
+        matches!(
+            self.opcode,
+            //ADC, AND, CMP, EOR
+            0x7D | 0x79 | 0x71 | 0x3D | 0x39 | 0x31 | 0xDD | 0xD9 | 0xD1 | 0x5D | 0x59 | 0x51
+            //LDA, LDX, LDY, ORA, SBC
+            | 0xBD | 0xB9 | 0xB1 | 0xBE | 0xBC | 0x1D | 0x19 | 0x11 | 0xFD | 0xF9 | 0xF1
+            //LAX, LAS, NOP
+            | 0xBF | 0xB3 | 0xBB | 0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC
+        )
+    }
 }
@@ -0,0 +1,366 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::eeprom::{Eeprom, EepromChip};
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_PAGE_SIZE: usize = 0x4000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x0400;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+//Маппер 153 использует бит 0 регистров CHR как старший банк PRG по 256 KiB
+const OUTER_PRG_PAGES: usize = 16;
+
+//Bandai FCG-1/FCG-2 и LZ93D50 (мапперы 16, 153, 157, 159)
+pub struct Bandai {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    battery: bool,
+    mapper: u16,
+    //FCG пишет счетчик IRQ напрямую и держит регистры в $6000-$7FFF
+    fcg: bool,
+    lz93d50: bool,
+
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    outer_prg_bank: u8,
+    prg_ram_enabled: bool,
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_pending: bool,
+
+    eeprom: Option<Eeprom>,
+    eeprom_read_enabled: bool,
+}
+
+#[allow(dead_code)]
+impl Bandai {
+    pub fn new(rom: &Rom) -> Self {
+        let chr_ram = rom.chr_rom.is_empty();
+        let chr = match chr_ram {
+            true => vec![0; CHR_RAM_SIZE],
+            false => rom.chr_rom.clone(),
+        };
+
+        //Сабмаппер 4 - FCG, 5 - LZ93D50, 0 - неизвестно, декодируем оба диапазона
+        let (fcg, lz93d50) = match (rom.mapper, rom.submapper) {
+            (16, 4) => (true, false),
+            (16, 5) => (false, true),
+            (16, _) => (true, true),
+            _ => (false, true),
+        };
+
+        let eeprom = match (rom.mapper, fcg && !lz93d50) {
+            (16, false) | (157, _) => Some(Eeprom::new(EepromChip::X24C02)),
+            (159, _) => Some(Eeprom::new(EepromChip::X24C01)),
+            _ => None,
+        };
+
+        let prg_ram = match rom.mapper {
+            153 => vec![0; PRG_RAM_SIZE],
+            _ => vec![],
+        };
+
+        Bandai {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram,
+            chr,
+            chr_ram,
+            battery: rom.battery,
+            mapper: rom.mapper,
+            fcg,
+            lz93d50,
+
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            outer_prg_bank: 0,
+            prg_ram_enabled: false,
+            mirroring: Mirroring::Vertical,
+
+            irq_enabled: false,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_pending: false,
+
+            eeprom,
+            eeprom_read_enabled: false,
+        }
+    }
+
+    fn read_prg(&self, page: usize, address: u16) -> u8 {
+        let index = page * PRG_PAGE_SIZE + (address as usize & 0x3FFF);
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        match self.mapper {
+            153 => address as usize % self.chr.len(),
+            _ => {
+                let bank = self.chr_banks[address as usize / CHR_PAGE_SIZE] as usize;
+                (bank * CHR_PAGE_SIZE + (address as usize % CHR_PAGE_SIZE)) % self.chr.len()
+            }
+        }
+    }
+
+    fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            0x0..=0x7 => {
+                self.chr_banks[register as usize] = value;
+                self.outer_prg_bank = value & 1;
+            }
+            0x8 => self.prg_bank = value & 0x0F,
+            0x9 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                }
+            }
+            0xA => {
+                self.irq_enabled = value & 1 != 0;
+                self.irq_pending = false;
+                if self.lz93d50 && !self.fcg {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xB => match self.lz93d50 && !self.fcg {
+                true => self.irq_latch = (self.irq_latch & 0xFF00) | value as u16,
+                false => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            },
+            0xC => match self.lz93d50 && !self.fcg {
+                true => self.irq_latch = (self.irq_latch & 0x00FF) | (value as u16) << 8,
+                false => self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16) << 8,
+            },
+            0xD => {
+                self.prg_ram_enabled = value & 0b0010_0000 != 0;
+                self.eeprom_read_enabled = value & 0b1000_0000 != 0;
+                if let Some(eeprom) = self.eeprom.as_mut() {
+                    eeprom.write(value & 0b0010_0000 != 0, value & 0b0100_0000 != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Bandai {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.mapper == 153 && self.prg_ram_enabled => {
+                Some(self.prg_ram[(address - 0x6000) as usize])
+            }
+            0x8000..=0xBFFF => {
+                let page = match self.mapper {
+                    153 => self.outer_prg_bank as usize * OUTER_PRG_PAGES + self.prg_bank as usize,
+                    _ => self.prg_bank as usize,
+                };
+                Some(self.read_prg(page, address))
+            }
+            0xC000..=0xFFFF => {
+                let page = match self.mapper {
+                    153 => self.outer_prg_bank as usize * OUTER_PRG_PAGES + OUTER_PRG_PAGES - 1,
+                    _ => self.prg_rom.len() / PRG_PAGE_SIZE - 1,
+                };
+                Some(self.read_prg(page, address))
+            }
+            _ => None,
+        }
+    }
+
+    //EEPROM выставляет на шину только бит 4, остальные биты - открытая шина
+    fn cpu_read_open_bus(&mut self, address: u16, open_bus: u8) -> Option<u8> {
+        match (address, &self.eeprom, self.eeprom_read_enabled) {
+            (0x6000..=0x7FFF, Some(eeprom), true) => {
+                Some(open_bus & !0x10 | (eeprom.output() as u8) << 4)
+            }
+            (0x6000..=0x7FFF, Some(_), false) => None,
+            _ => self.cpu_read(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.mapper == 153 && self.prg_ram_enabled => {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            0x6000..=0x7FFF if self.fcg => self.write_register(address & 0x0F, value),
+            0x8000..=0xFFFF if self.lz93d50 => self.write_register(address & 0x0F, value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_address(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let index = self.chr_address(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+
+        //Проверка до уменьшения счетчика, иначе часть игр дергает экран
+        if self.irq_counter == 0 {
+            self.irq_pending = true;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match (&self.eeprom, self.battery && !self.prg_ram.is_empty()) {
+            (Some(eeprom), _) => Some(eeprom.data.clone()),
+            (None, true) => Some(self.prg_ram.clone()),
+            _ => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let memory = match self.eeprom.as_mut() {
+            Some(eeprom) => &mut eeprom.data,
+            None => &mut self.prg_ram,
+        };
+
+        let len = std::cmp::min(data.len(), memory.len());
+        memory[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod bandai_test {
+    use super::*;
+
+    fn board(mapper: u16, submapper: u8) -> Bandai {
+        let rom = Rom::test(mapper, submapper, vec![0; 0x40000], vec![0; 0x40000]);
+
+        Bandai::new(&rom)
+    }
+
+    fn set_lines(bandai: &mut Bandai, scl: bool, sda: bool) {
+        bandai.cpu_write(0x800D, (scl as u8) << 5 | (sda as u8) << 6 | 0x80);
+    }
+
+    fn start(bandai: &mut Bandai) {
+        set_lines(bandai, true, true);
+        set_lines(bandai, true, false);
+        set_lines(bandai, false, false);
+    }
+
+    fn stop(bandai: &mut Bandai) {
+        set_lines(bandai, false, false);
+        set_lines(bandai, true, false);
+        set_lines(bandai, true, true);
+    }
+
+    fn send_bit(bandai: &mut Bandai, bit: bool) {
+        set_lines(bandai, false, bit);
+        set_lines(bandai, true, bit);
+        set_lines(bandai, false, bit);
+    }
+
+    fn read_bit(bandai: &mut Bandai) -> bool {
+        set_lines(bandai, false, true);
+        set_lines(bandai, true, true);
+        let bit = bandai.cpu_read_open_bus(0x6000, 0).unwrap() & 0x10 != 0;
+        set_lines(bandai, false, true);
+        bit
+    }
+
+    //Отправляет байт старшим битом вперед и возвращает подтверждение
+    fn send_byte(bandai: &mut Bandai, value: u8) -> bool {
+        for bit in (0..8).rev() {
+            send_bit(bandai, (value >> bit) & 1 != 0);
+        }
+        !read_bit(bandai)
+    }
+
+    fn read_byte(bandai: &mut Bandai, ack: bool) -> u8 {
+        let mut value = 0;
+        for _ in 0..8 {
+            value = value << 1 | read_bit(bandai) as u8;
+        }
+        send_bit(bandai, !ack);
+        value
+    }
+
+    #[test]
+    fn test_24c02_write_and_read() {
+        let mut bandai = board(16, 5);
+
+        start(&mut bandai);
+        assert!(send_byte(&mut bandai, 0xA0));
+        assert!(send_byte(&mut bandai, 0x10));
+        assert!(send_byte(&mut bandai, 0x12));
+        assert!(send_byte(&mut bandai, 0x34));
+        stop(&mut bandai);
+
+        assert_eq!(&bandai.save_data().unwrap()[0x10..0x12], &[0x12, 0x34]);
+
+        start(&mut bandai);
+        assert!(send_byte(&mut bandai, 0xA0));
+        assert!(send_byte(&mut bandai, 0x10));
+        start(&mut bandai);
+        assert!(send_byte(&mut bandai, 0xA1));
+        assert_eq!(read_byte(&mut bandai, true), 0x12);
+        assert_eq!(read_byte(&mut bandai, false), 0x34);
+        stop(&mut bandai);
+    }
+
+    #[test]
+    fn test_eeprom_read_enable() {
+        let mut bandai = board(159, 0);
+        bandai.cpu_write(0x800D, 0b0110_0000);
+        assert_eq!(bandai.cpu_read_open_bus(0x6000, 0xA5), None);
+
+        //Линия SDA отпущена - читается 1 в бите 4, остальное с открытой шины
+        bandai.cpu_write(0x800D, 0b1110_0000);
+        assert_eq!(bandai.cpu_read_open_bus(0x6000, 0xA5), Some(0xB5));
+        assert_eq!(bandai.cpu_read_open_bus(0x7FFF, 0x00), Some(0x10));
+    }
+
+    #[test]
+    fn test_lz93d50_irq_latch() {
+        let mut bandai = board(159, 0);
+        bandai.cpu_write(0x800B, 0x01);
+        bandai.cpu_write(0x800C, 0x00);
+        bandai.cpu_write(0x800A, 0x01);
+
+        bandai.cpu_clock();
+        assert!(!bandai.irq());
+
+        bandai.cpu_clock();
+        assert!(bandai.irq());
+
+        bandai.cpu_write(0x800A, 0x00);
+        assert!(!bandai.irq());
+    }
+
+    #[test]
+    fn test_fcg_registers() {
+        let mut bandai = board(16, 4);
+        bandai.cpu_write(0x8009, 1);
+        assert_eq!(bandai.mirroring(), Mirroring::Vertical);
+
+        bandai.cpu_write(0x6009, 1);
+        assert_eq!(bandai.mirroring(), Mirroring::Horizontal);
+        assert!(bandai.save_data().is_none());
+    }
+}
@@ -0,0 +1,180 @@
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EepromChip {
+    //128 байт, без адреса устройства, биты идут младшим вперед
+    X24C01,
+    //256 байт, стандартный I2C, биты идут старшим вперед
+    X24C02,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    Device,
+    Address,
+    Write,
+    Read,
+}
+
+//Последовательная EEPROM, управляемая программно через линии SCL/SDA
+pub struct Eeprom {
+    chip: EepromChip,
+    pub data: Vec<u8>,
+
+    phase: Phase,
+    bit: u8,
+    shift: u8,
+    address: u8,
+    ack: bool,
+    output: bool,
+
+    scl: bool,
+    sda: bool,
+}
+
+impl Eeprom {
+    pub fn new(chip: EepromChip) -> Self {
+        let size = match chip {
+            EepromChip::X24C01 => 128,
+            EepromChip::X24C02 => 256,
+        };
+
+        Eeprom {
+            chip,
+            data: vec![0; size],
+
+            phase: Phase::Idle,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            ack: false,
+            output: true,
+
+            scl: false,
+            sda: false,
+        }
+    }
+
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        match (self.scl, scl) {
+            (true, true) if self.sda && !sda => self.start(),
+            (true, true) if !self.sda && sda => self.stop(),
+            (false, true) => self.rise(sda),
+            (true, false) => self.fall(),
+            _ => {}
+        }
+
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    //Состояние линии SDA со стороны микросхемы
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    fn start(&mut self) {
+        self.phase = match self.chip {
+            EepromChip::X24C01 => Phase::Address,
+            EepromChip::X24C02 => Phase::Device,
+        };
+        self.bit = 0;
+        self.shift = 0;
+        self.output = true;
+    }
+
+    fn stop(&mut self) {
+        self.phase = Phase::Idle;
+        self.output = true;
+    }
+
+    fn rise(&mut self, sda: bool) {
+        if self.phase == Phase::Idle {
+            return;
+        }
+
+        //Девятый такт - подтверждение, после отправленного байта его выставляет мастер
+        if self.bit == 8 {
+            self.bit = 0;
+            self.shift = 0;
+
+            if self.phase == Phase::Read && !self.ack {
+                match sda {
+                    true => self.phase = Phase::Idle,
+                    false => self.address = self.next_address(self.address.wrapping_add(1)),
+                }
+            }
+            return;
+        }
+
+        if self.phase != Phase::Read {
+            self.shift = match self.chip {
+                EepromChip::X24C01 => self.shift | (sda as u8) << self.bit,
+                EepromChip::X24C02 => self.shift << 1 | sda as u8,
+            };
+        }
+
+        self.bit += 1;
+        if self.bit == 8 {
+            self.receive_byte();
+        }
+    }
+
+    fn fall(&mut self) {
+        self.output = match (self.bit, self.phase) {
+            (8, _) => !self.ack,
+            (bit, Phase::Read) => {
+                let value = self.data[self.address as usize];
+                match self.chip {
+                    EepromChip::X24C01 => (value >> bit) & 1 != 0,
+                    EepromChip::X24C02 => (value >> (7 - bit)) & 1 != 0,
+                }
+            }
+            _ => true,
+        };
+    }
+
+    fn receive_byte(&mut self) {
+        self.ack = true;
+
+        match (self.phase, self.chip) {
+            (Phase::Device, _) => match self.shift & 0xF0 {
+                0xA0 if self.shift & 1 != 0 => self.phase = Phase::Read,
+                0xA0 => self.phase = Phase::Address,
+                _ => {
+                    self.phase = Phase::Idle;
+                    self.ack = false;
+                }
+            },
+            (Phase::Address, EepromChip::X24C01) => {
+                self.address = self.shift & 0x7F;
+                self.phase = match self.shift & 0x80 {
+                    0 => Phase::Write,
+                    _ => Phase::Read,
+                };
+            }
+            (Phase::Address, EepromChip::X24C02) => {
+                self.address = self.shift;
+                self.phase = Phase::Write;
+            }
+            (Phase::Write, _) => {
+                self.data[self.address as usize] = self.shift;
+                self.address = self.next_address(self.address.wrapping_add(1));
+            }
+            _ => self.ack = false,
+        }
+    }
+
+    //Запись страницами: 4 байта у 24C01 и 8 у 24C02, адрес в пределах страницы зацикливается
+    fn next_address(&self, address: u8) -> u8 {
+        match self.phase {
+            Phase::Write => {
+                let page_mask = match self.chip {
+                    EepromChip::X24C01 => 0b011,
+                    EepromChip::X24C02 => 0b111,
+                };
+                (self.address & !page_mask) | (address & page_mask)
+            }
+            _ => (address as usize % self.data.len()) as u8,
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_PAGE_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x0400;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+//Счетчики 5B тактируются раз в 16 тактов CPU
+const AUDIO_DIVIDER: u8 = 16;
+//Громкость 5B меняется на 1.5 дБ за шаг огибающей
+const VOLUME_STEP_DB: f32 = 1.5;
+const AUDIO_SCALE: f32 = 0.08;
+
+#[derive(Default)]
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+}
+
+impl Tone {
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+}
+
+//Звуковой чип Sunsoft 5B, совместимый с AY-3-8910
+struct Sunsoft5b {
+    register: u8,
+    tones: [Tone; 3],
+    volumes: [u8; 3],
+    mixer: u8,
+
+    noise_period: u8,
+    noise_counter: u8,
+    noise_shift: u32,
+
+    envelope_period: u16,
+    envelope_counter: u16,
+    envelope_shape: u8,
+    envelope_step: u8,
+    envelope_attack: u8,
+    envelope_holding: bool,
+
+    divider: u8,
+    volume_table: [f32; 32],
+}
+
+impl Sunsoft5b {
+    fn new() -> Self {
+        let mut volume_table = [0.0; 32];
+        for (level, volume) in volume_table.iter_mut().enumerate().skip(1) {
+            *volume = 10f32.powf((level as f32 - 31.0) * VOLUME_STEP_DB / 20.0);
+        }
+
+        Sunsoft5b {
+            register: 0,
+            tones: [Tone::default(), Tone::default(), Tone::default()],
+            volumes: [0; 3],
+            mixer: 0,
+
+            noise_period: 0,
+            noise_counter: 0,
+            noise_shift: 1,
+
+            envelope_period: 0,
+            envelope_counter: 0,
+            envelope_shape: 0,
+            envelope_step: 0,
+            envelope_attack: 0,
+            envelope_holding: true,
+
+            divider: 0,
+            volume_table,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        match self.register {
+            0x00..=0x05 => {
+                let tone = &mut self.tones[(self.register / 2) as usize];
+                tone.period = match self.register & 1 {
+                    0 => (tone.period & 0x0F00) | value as u16,
+                    _ => (tone.period & 0x00FF) | ((value as u16 & 0x0F) << 8),
+                };
+            }
+            0x06 => self.noise_period = value & 0x1F,
+            0x07 => self.mixer = value,
+            0x08..=0x0A => self.volumes[(self.register - 0x08) as usize] = value & 0x1F,
+            0x0B => self.envelope_period = (self.envelope_period & 0xFF00) | value as u16,
+            0x0C => self.envelope_period = (self.envelope_period & 0x00FF) | (value as u16) << 8,
+            0x0D => {
+                self.envelope_shape = value & 0x0F;
+                self.envelope_counter = 0;
+                self.envelope_step = 31;
+                self.envelope_holding = false;
+                self.envelope_attack = match value & 0b0100 {
+                    0 => 0,
+                    _ => 31,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < AUDIO_DIVIDER {
+            return;
+        }
+        self.divider = 0;
+
+        self.tones.iter_mut().for_each(Tone::clock);
+
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period {
+            self.envelope_counter = 0;
+            self.clock_envelope();
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+
+        if self.envelope_step > 0 {
+            self.envelope_step -= 1;
+            return;
+        }
+
+        let cont = self.envelope_shape & 0b1000 != 0;
+        let alternate = self.envelope_shape & 0b0010 != 0;
+        let hold = self.envelope_shape & 0b0001 != 0;
+
+        match (cont, hold) {
+            (false, _) => {
+                self.envelope_attack = 0;
+                self.envelope_holding = true;
+            }
+            (true, true) => {
+                if alternate {
+                    self.envelope_attack ^= 31;
+                }
+                self.envelope_holding = true;
+            }
+            (true, false) => {
+                if alternate {
+                    self.envelope_attack ^= 31;
+                }
+                self.envelope_step = 31;
+            }
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let noise = self.noise_shift & 1 != 0;
+
+        (0..3)
+            .map(|channel| {
+                let tone_on = self.tones[channel].output || self.mixer & (1 << channel) != 0;
+                let noise_on = noise || self.mixer & (1 << (channel + 3)) != 0;
+
+                if !(tone_on && noise_on) {
+                    return 0.0;
+                }
+
+                let level = match self.volumes[channel] & 0x10 {
+                    0 => match self.volumes[channel] & 0x0F {
+                        0 => 0,
+                        volume => volume * 2 + 1,
+                    },
+                    _ => self.envelope_step ^ self.envelope_attack,
+                };
+
+                self.volume_table[level as usize]
+            })
+            .sum()
+    }
+}
+
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    battery: bool,
+
+    command: u8,
+    chr_banks: [u8; 8],
+    //Команда 8: банк $6000, бит 6 - RAM, бит 7 - RAM включена
+    prg_bank_6000: u8,
+    prg_banks: [u8; 3],
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Sunsoft5b,
+}
+
+#[allow(dead_code)]
+impl Fme7 {
+    pub fn new(rom: &Rom) -> Self {
+        let chr_ram = rom.chr_rom.is_empty();
+        let chr = match chr_ram {
+            true => vec![0; CHR_RAM_SIZE],
+            false => rom.chr_rom.clone(),
+        };
+
+        Fme7 {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr,
+            chr_ram,
+            battery: rom.battery,
+
+            command: 0,
+            chr_banks: [0; 8],
+            prg_bank_6000: 0,
+            prg_banks: [0; 3],
+            mirroring: Mirroring::Vertical,
+
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn read_prg(&self, page: usize, address: u16) -> u8 {
+        let index = page * PRG_PAGE_SIZE + (address as usize & 0x1FFF);
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        let bank = self.chr_banks[address as usize / CHR_PAGE_SIZE] as usize;
+        (bank * CHR_PAGE_SIZE + (address as usize % CHR_PAGE_SIZE)) % self.chr.len()
+    }
+
+    fn prg_ram_selected(&self) -> bool {
+        self.prg_bank_6000 & 0b0100_0000 != 0
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank_6000 & 0b1000_0000 != 0
+    }
+
+    fn execute_command(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+            0x8 => self.prg_bank_6000 = value,
+            0x9..=0xB => self.prg_banks[(self.command - 0x9) as usize] = value & 0x3F,
+            0xC => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                }
+            }
+            0xD => {
+                self.irq_enabled = value & 0b0000_0001 != 0;
+                self.irq_counter_enabled = value & 0b1000_0000 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF => match (self.prg_ram_selected(), self.prg_ram_enabled()) {
+                (false, _) => Some(self.read_prg((self.prg_bank_6000 & 0x3F) as usize, address)),
+                (true, true) => Some(self.prg_ram[(address - 0x6000) as usize]),
+                (true, false) => None,
+            },
+            0x8000..=0x9FFF => Some(self.read_prg(self.prg_banks[0] as usize, address)),
+            0xA000..=0xBFFF => Some(self.read_prg(self.prg_banks[1] as usize, address)),
+            0xC000..=0xDFFF => Some(self.read_prg(self.prg_banks[2] as usize, address)),
+            0xE000..=0xFFFF => {
+                let last_page = self.prg_rom.len() / PRG_PAGE_SIZE - 1;
+                Some(self.read_prg(last_page, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_selected() && self.prg_ram_enabled() => {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.execute_command(value),
+            0xC000..=0xDFFF => self.audio.register = value & 0x0F,
+            0xE000..=0xFFFF => self.audio.write(value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_address(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let index = self.chr_address(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output() * AUDIO_SCALE
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match self.battery {
+            true => Some(self.prg_ram.clone()),
+            false => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = std::cmp::min(data.len(), self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod fme7_test {
+    use super::*;
+
+    fn fme7() -> Fme7 {
+        let mut prg_rom = vec![0; 0x40000];
+        for (page, chunk) in prg_rom.chunks_mut(PRG_PAGE_SIZE).enumerate() {
+            chunk[0] = page as u8;
+        }
+
+        let mut rom = Rom::test(69, 0, prg_rom, vec![0; 0x40000]);
+        rom.prg_ram_size = PRG_RAM_SIZE;
+
+        Fme7::new(&rom)
+    }
+
+    fn command(fme7: &mut Fme7, command: u8, parameter: u8) {
+        fme7.cpu_write(0x8000, command);
+        fme7.cpu_write(0xA000, parameter);
+    }
+
+    #[test]
+    fn test_prg_ram_select() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0x8, 0x05);
+        assert_eq!(fme7.cpu_read(0x6000), Some(5));
+
+        command(&mut fme7, 0x8, 0b0100_0000);
+        assert_eq!(fme7.cpu_read(0x6000), None);
+
+        command(&mut fme7, 0x8, 0b1100_0000);
+        fme7.cpu_write(0x6000, 0x42);
+        assert_eq!(fme7.cpu_read(0x6000), Some(0x42));
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 0xE, 0x01);
+        command(&mut fme7, 0xF, 0x00);
+        command(&mut fme7, 0xD, 0b1000_0001);
+
+        fme7.cpu_clock();
+        assert!(!fme7.irq());
+
+        fme7.cpu_clock();
+        assert!(fme7.irq());
+
+        command(&mut fme7, 0xD, 0b1000_0001);
+        assert!(!fme7.irq());
+    }
+
+    #[test]
+    fn test_envelope_hold() {
+        let mut audio = Sunsoft5b::new();
+        audio.register = 0x0D;
+        audio.write(0b1101);
+
+        assert_eq!(audio.envelope_step ^ audio.envelope_attack, 0);
+
+        for _ in 0..40 {
+            audio.clock_envelope();
+        }
+
+        assert!(audio.envelope_holding);
+        assert_eq!(audio.envelope_step ^ audio.envelope_attack, 31);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x1000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Latch {
+    FD,
+    FE,
+}
+
+//MMC2 (маппер 9) и MMC4 (маппер 10). Банки CHR переключаются сами,
+//когда PPU выбирает тайл $FD или $FE
+pub struct Mmc2 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_rom: Vec<u8>,
+    battery: bool,
+    mmc4: bool,
+
+    prg_bank: u8,
+    //[$0000 FD, $0000 FE, $1000 FD, $1000 FE]
+    chr_banks: [u8; 4],
+    latches: [Latch; 2],
+    mirroring: Mirroring,
+}
+
+#[allow(dead_code)]
+impl Mmc2 {
+    pub fn new(rom: &Rom) -> Self {
+        Mmc2 {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_rom: rom.chr_rom.clone(),
+            battery: rom.battery,
+            mmc4: rom.mapper == 10,
+
+            prg_bank: 0,
+            chr_banks: [0; 4],
+            latches: [Latch::FE; 2],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+
+    fn read_prg(&self, page: usize, size: usize, address: u16) -> u8 {
+        let index = page * size + (address as usize & (size - 1));
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    fn prg_pages(&self, size: usize) -> usize {
+        self.prg_rom.len() / size
+    }
+
+    //Защелка переключается после выборки, сама выборка идет из старого банка
+    fn update_latch(&mut self, address: u16) {
+        let latch = match (address, self.mmc4) {
+            (0x0FD8, false) | (0x0FD8..=0x0FDF, true) => (0, Latch::FD),
+            (0x0FE8, false) | (0x0FE8..=0x0FEF, true) => (0, Latch::FE),
+            (0x1FD8..=0x1FDF, _) => (1, Latch::FD),
+            (0x1FE8..=0x1FEF, _) => (1, Latch::FE),
+            _ => return,
+        };
+
+        self.latches[latch.0] = latch.1;
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match (address, self.mmc4) {
+            (0x6000..=0x7FFF, _) => Some(self.prg_ram[(address - 0x6000) as usize]),
+            (0x8000..=0x9FFF, false) => {
+                Some(self.read_prg(self.prg_bank as usize, 0x2000, address))
+            }
+            (0xA000..=0xFFFF, false) => {
+                let page = self.prg_pages(0x2000) - 4 + ((address - 0x8000) / 0x2000) as usize;
+                Some(self.read_prg(page, 0x2000, address))
+            }
+            (0x8000..=0xBFFF, true) => Some(self.read_prg(self.prg_bank as usize, 0x4000, address)),
+            (0xC000..=0xFFFF, true) => {
+                let last_page = self.prg_pages(0x4000) - 1;
+                Some(self.read_prg(last_page, 0x4000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram[(address - 0x6000) as usize] = value,
+            0xA000..=0xAFFF => self.prg_bank = value & 0x0F,
+            0xB000..=0xEFFF => {
+                self.chr_banks[((address - 0xB000) / 0x1000) as usize] = value & 0x1F;
+            }
+            0xF000..=0xFFFF => {
+                self.mirroring = match value & 1 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        let value = self.peek_chr(address);
+        self.update_latch(address);
+
+        value
+    }
+
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        let half = (address >> 12) as usize;
+        let bank = match self.latches[half] {
+            Latch::FD => self.chr_banks[half * 2],
+            Latch::FE => self.chr_banks[half * 2 + 1],
+        };
+
+        let index = bank as usize * CHR_PAGE_SIZE + (address as usize & 0x0FFF);
+        self.chr_rom[index % self.chr_rom.len()]
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match self.battery {
+            true => Some(self.prg_ram.clone()),
+            false => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = std::cmp::min(data.len(), self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod mmc2_test {
+    use super::*;
+
+    fn board(mapper: u16) -> Mmc2 {
+        let mut chr_rom = vec![0; 0x20000];
+        for (page, chunk) in chr_rom.chunks_mut(CHR_PAGE_SIZE).enumerate() {
+            for byte in chunk.iter_mut() {
+                *byte = page as u8;
+            }
+        }
+
+        let mut rom = Rom::test(mapper, 0, vec![0; 0x20000], chr_rom);
+        rom.prg_ram_size = PRG_RAM_SIZE;
+
+        Mmc2::new(&rom)
+    }
+
+    #[test]
+    fn test_latch_switch_after_fetch() {
+        let mut mmc2 = board(9);
+        mmc2.cpu_write(0xB000, 4);
+        mmc2.cpu_write(0xC000, 5);
+
+        assert_eq!(mmc2.ppu_read(0x0000), 5);
+        assert_eq!(mmc2.ppu_read(0x0FD8), 5);
+        assert_eq!(mmc2.ppu_read(0x0000), 4);
+
+        mmc2.ppu_read(0x0FE8);
+        assert_eq!(mmc2.ppu_read(0x0000), 5);
+    }
+
+    #[test]
+    fn test_mmc2_exact_latch_address() {
+        let mut mmc2 = board(9);
+        mmc2.cpu_write(0xB000, 4);
+        mmc2.cpu_write(0xC000, 5);
+
+        mmc2.ppu_read(0x0FD9);
+        assert_eq!(mmc2.ppu_read(0x0000), 5);
+
+        let mut mmc4 = board(10);
+        mmc4.cpu_write(0xB000, 4);
+        mmc4.cpu_write(0xC000, 5);
+
+        mmc4.ppu_read(0x0FD9);
+        assert_eq!(mmc4.ppu_read(0x0000), 4);
+    }
+
+    #[test]
+    fn test_upper_latch() {
+        let mut mmc2 = board(9);
+        mmc2.cpu_write(0xD000, 6);
+        mmc2.cpu_write(0xE000, 7);
+
+        mmc2.ppu_read(0x1FDD);
+        assert_eq!(mmc2.ppu_read(0x1000), 6);
+        assert_eq!(mmc2.ppu_read(0x0000), 0);
+    }
+}
@@ -0,0 +1,832 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_PAGE_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x0400;
+const CHR_RAM_SIZE: usize = 0x2000;
+const EXRAM_SIZE: usize = 0x0400;
+
+//Сколько тактов CPU без чтений PPU до сброса флага "in frame"
+const PPU_IDLE_CYCLES: u8 = 3;
+
+//Порядок чтений PPU за строку, начиная с момента обнаружения строки:
+//фон (тайлы 2-33), спрайты, фон (тайлы 0-1 следующей строки), два холостых чтения
+const BACKGROUND_FETCHES: u16 = 128;
+const SPRITE_FETCHES_END: u16 = 160;
+const PREFETCH_END: u16 = 168;
+
+//Частота тактирования огибающей и счетчика длины (~240 Гц)
+const FRAME_PERIOD: u16 = 7457;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExRamMode {
+    Nametable,
+    ExtendedAttribute,
+    Ram,
+    ReadOnlyRam,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fetch {
+    Background(u16, u16),
+    Sprite,
+    Other,
+}
+
+#[derive(Default)]
+struct Pulse {
+    enabled: bool,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    length: u8,
+    halt: bool,
+    constant_volume: bool,
+    volume: u8,
+    envelope_start: bool,
+    envelope_divider: u8,
+    envelope_decay: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.halt = value & 0b0010_0000 != 0;
+                self.constant_volume = value & 0b0001_0000 != 0;
+                self.volume = value & 0x0F;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | value as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0b111) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+                self.step = 0;
+                self.envelope_start = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period;
+                self.step = (self.step + 1) & 0b111;
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    fn clock_frame(&mut self) {
+        if self.envelope_start {
+            self.envelope_start = false;
+            self.envelope_decay = 15;
+            self.envelope_divider = self.volume;
+        } else if self.envelope_divider == 0 {
+            self.envelope_divider = self.volume;
+            if self.envelope_decay > 0 {
+                self.envelope_decay -= 1;
+            } else if self.halt {
+                self.envelope_decay = 15;
+            }
+        } else {
+            self.envelope_divider -= 1;
+        }
+
+        if !self.halt && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    //В отличие от 2A03 малые периоды канал не глушат
+    fn output(&self) -> u8 {
+        if self.length == 0 || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            return 0;
+        }
+
+        match self.constant_volume {
+            true => self.volume,
+            false => self.envelope_decay,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pcm {
+    read_mode: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    output: u8,
+}
+
+impl Pcm {
+    fn load(&mut self, value: u8) {
+        //Ноль не попадает в ЦАП, а прерывание вызывает только в режиме чтения
+        match (value, self.read_mode) {
+            (0, true) => self.irq_pending = true,
+            (0, false) => {}
+            _ => self.output = value,
+        }
+    }
+}
+
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    battery: bool,
+    exram: [u8; EXRAM_SIZE],
+
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: ExRamMode,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    //$5113-$5117
+    prg_banks: [u8; 5],
+    //$5120-$512B
+    chr_banks: [u16; 12],
+    chr_upper: u8,
+    last_chr_write_b: bool,
+
+    large_sprites: bool,
+    rendering_enabled: bool,
+
+    in_frame: bool,
+    scanline: u16,
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    last_ppu_address: u16,
+    nametable_reads: u8,
+    fetch_index: u16,
+    ppu_idle: u8,
+
+    split_enabled: bool,
+    split_right: bool,
+    split_tile: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    split_fetch: bool,
+    split_y: u16,
+    extended_attribute: u8,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    pulses: [Pulse; 2],
+    pcm: Pcm,
+    frame_divider: u16,
+    odd_cycle: bool,
+}
+
+#[allow(dead_code)]
+impl Mmc5 {
+    pub fn new(rom: &Rom) -> Self {
+        let chr_ram = rom.chr_rom.is_empty();
+        let chr = match chr_ram {
+            true => vec![0; CHR_RAM_SIZE],
+            false => rom.chr_rom.clone(),
+        };
+
+        Mmc5 {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram: vec![0; rom.prg_ram_size + rom.prg_nvram_size],
+            chr,
+            chr_ram,
+            battery: rom.battery,
+            exram: [0; EXRAM_SIZE],
+
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: ExRamMode::Nametable,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            last_chr_write_b: false,
+
+            large_sprites: false,
+            rendering_enabled: false,
+
+            in_frame: false,
+            scanline: 0,
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            last_ppu_address: 0,
+            nametable_reads: 0,
+            fetch_index: u16::MAX,
+            ppu_idle: 0,
+
+            split_enabled: false,
+            split_right: false,
+            split_tile: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            split_fetch: false,
+            split_y: 0,
+            extended_attribute: 0,
+
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+
+            pulses: [Pulse::default(), Pulse::default()],
+            pcm: Pcm::default(),
+            frame_divider: 0,
+            odd_cycle: false,
+        }
+    }
+
+    //Возвращает (ROM?, номер 8 KiB страницы) для $8000-$FFFF
+    fn prg_page(&self, address: u16) -> (bool, usize) {
+        let slot = ((address - 0x8000) as usize) / PRG_PAGE_SIZE;
+
+        let (register, pages) = match (self.prg_mode, slot) {
+            (0, _) => (4, 4),
+            (1, 0..=1) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 0..=1) => (2, 2),
+            (2, slot) => (slot + 1, 1),
+            (_, slot) => (slot + 1, 1),
+        };
+
+        let value = self.prg_banks[register];
+        let rom = register == 4 || value & 0x80 != 0;
+        let page = (value & 0x7F) as usize & !(pages - 1);
+
+        (rom, page + slot % pages)
+    }
+
+    fn prg_ram_address(&self, page: u8, address: u16) -> Option<usize> {
+        match self.prg_ram.len() {
+            0 => None,
+            len => Some((page as usize * PRG_PAGE_SIZE + (address as usize & 0x1FFF)) % len),
+        }
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    fn fetch(&self) -> Fetch {
+        match self.fetch_index {
+            index if index < BACKGROUND_FETCHES => Fetch::Background(2 + index / 4, self.scanline),
+            index if index < SPRITE_FETCHES_END => Fetch::Sprite,
+            index if index < PREFETCH_END => {
+                Fetch::Background((index - SPRITE_FETCHES_END) / 4, self.scanline + 1)
+            }
+            _ => Fetch::Other,
+        }
+    }
+
+    fn in_split(&self, column: u16) -> bool {
+        match self.exram_mode {
+            ExRamMode::Nametable | ExRamMode::ExtendedAttribute if self.split_enabled => {}
+            _ => return false,
+        }
+
+        match self.split_right {
+            true => column >= self.split_tile as u16,
+            false => column < self.split_tile as u16,
+        }
+    }
+
+    //Каждое чтение PPU сдвигает счетчик выборок, три одинаковых чтения
+    //таблицы имен подряд означают начало новой строки
+    fn track_ppu_read(&mut self, address: u16) {
+        let nametable = (0x2000..0x3000).contains(&address);
+
+        match nametable && address == self.last_ppu_address {
+            true => self.nametable_reads += 1,
+            false => self.nametable_reads = 0,
+        }
+
+        self.last_ppu_address = address;
+        self.ppu_idle = PPU_IDLE_CYCLES;
+        self.fetch_index = self.fetch_index.saturating_add(1);
+
+        if self.nametable_reads == 2 {
+            self.nametable_reads = 0;
+            self.fetch_index = 0;
+
+            match self.in_frame {
+                true => {
+                    self.scanline += 1;
+                    if self.scanline == self.irq_target as u16 {
+                        self.irq_pending = true;
+                    }
+                }
+                false => {
+                    self.in_frame = true;
+                    self.scanline = 0;
+                    self.irq_pending = false;
+                }
+            }
+        }
+    }
+
+    fn leave_frame(&mut self) {
+        self.in_frame = false;
+        self.fetch_index = u16::MAX;
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        let background = match self.fetch() {
+            Fetch::Background(..) => true,
+            Fetch::Sprite => false,
+            Fetch::Other => self.last_chr_write_b,
+        };
+
+        self.bank_address(address, background)
+    }
+
+    fn bank_address(&self, address: u16, background: bool) -> usize {
+        let use_b = match self.large_sprites && self.in_frame {
+            true => background,
+            false => self.last_chr_write_b,
+        };
+
+        let size = CHR_PAGE_SIZE << (3 - self.chr_mode);
+        let pages = size / CHR_PAGE_SIZE;
+        let address = address as usize;
+
+        let (register, offset) = match use_b {
+            true => {
+                let pages_b = std::cmp::min(pages, 4);
+                let slot = (address & 0x0FFF) / (pages_b * CHR_PAGE_SIZE);
+                (8 + slot * pages_b + pages_b - 1, address % size)
+            }
+            false => {
+                let slot = address / size;
+                (slot * pages + pages - 1, address % size)
+            }
+        };
+
+        self.chr_banks[register] as usize * size + offset
+    }
+
+    fn read_chr(&self, address: usize) -> u8 {
+        self.chr[address % self.chr.len()]
+    }
+
+    fn write_chr_bank(&mut self, register: usize, value: u8) {
+        self.chr_banks[register] = value as u16 | (self.chr_upper as u16) << 8;
+        self.last_chr_write_b = register >= 8;
+    }
+
+    fn nametable_source(&self, address: u16) -> u8 {
+        let quadrant = (address >> 10) & 0b11;
+        (self.nametable_mapping >> (quadrant * 2)) & 0b11
+    }
+
+    fn clock_audio(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulses.iter_mut().for_each(Pulse::clock_timer);
+        }
+
+        self.frame_divider += 1;
+        if self.frame_divider == FRAME_PERIOD {
+            self.frame_divider = 0;
+            self.pulses.iter_mut().for_each(Pulse::clock_frame);
+        }
+    }
+}
+
+fn replicate_attribute(palette: u8) -> u8 {
+    (palette & 0b11) * 0b0101_0101
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x5010 => {
+                let value = (self.pcm.irq_pending as u8) << 7 | self.pcm.read_mode as u8;
+                self.pcm.irq_pending = false;
+                Some(value)
+            }
+            0x5015 => {
+                Some((self.pulses[0].length > 0) as u8 | ((self.pulses[1].length > 0) as u8) << 1)
+            }
+            0x5204 => {
+                let value = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                Some(value)
+            }
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5C00..=0x5FFF => match self.exram_mode {
+                ExRamMode::Ram | ExRamMode::ReadOnlyRam => {
+                    Some(self.exram[(address - 0x5C00) as usize])
+                }
+                _ => None,
+            },
+            0x6000..=0x7FFF => self
+                .prg_ram_address(self.prg_banks[0], address)
+                .map(|index| self.prg_ram[index]),
+            0x8000..=0xFFFF => {
+                //Выборка вектора NMI означает конец кадра
+                if address == 0xFFFA || address == 0xFFFB {
+                    self.leave_frame();
+                }
+
+                let value = match self.prg_page(address) {
+                    (true, page) => {
+                        let index = page * PRG_PAGE_SIZE + (address as usize & 0x1FFF);
+                        Some(self.prg_rom[index % self.prg_rom.len()])
+                    }
+                    (false, page) => self
+                        .prg_ram_address(page as u8, address)
+                        .map(|index| self.prg_ram[index]),
+                };
+
+                if self.pcm.read_mode && address < 0xC000 {
+                    if let Some(value) = value {
+                        self.pcm.load(value);
+                    }
+                }
+
+                value
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5003 => self.pulses[0].write(address - 0x5000, value),
+            0x5004..=0x5007 => self.pulses[1].write(address - 0x5004, value),
+            0x5010 => {
+                self.pcm.read_mode = value & 0b1 != 0;
+                self.pcm.irq_enabled = value & 0b1000_0000 != 0;
+            }
+            0x5011 if !self.pcm.read_mode => self.pcm.load(value),
+            0x5015 => {
+                self.pulses[0].set_enabled(value & 0b01 != 0);
+                self.pulses[1].set_enabled(value & 0b10 != 0);
+            }
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 => self.prg_ram_protect[0] = value & 0b11,
+            0x5103 => self.prg_ram_protect[1] = value & 0b11,
+            0x5104 => {
+                self.exram_mode = match value & 0b11 {
+                    0 => ExRamMode::Nametable,
+                    1 => ExRamMode::ExtendedAttribute,
+                    2 => ExRamMode::Ram,
+                    _ => ExRamMode::ReadOnlyRam,
+                }
+            }
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0b11,
+            0x5113..=0x5117 => self.prg_banks[(address - 0x5113) as usize] = value,
+            0x5120..=0x512B => self.write_chr_bank((address - 0x5120) as usize, value),
+            0x5130 => self.chr_upper = value & 0b11,
+            0x5200 => {
+                self.split_enabled = value & 0b1000_0000 != 0;
+                self.split_right = value & 0b0100_0000 != 0;
+                self.split_tile = value & 0b0001_1111;
+            }
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_target = value,
+            0x5204 => self.irq_enabled = value & 0b1000_0000 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let index = (address - 0x5C00) as usize;
+                match self.exram_mode {
+                    ExRamMode::Nametable | ExRamMode::ExtendedAttribute => {
+                        self.exram[index] = if self.in_frame { value } else { 0 };
+                    }
+                    ExRamMode::Ram => self.exram[index] = value,
+                    ExRamMode::ReadOnlyRam => {}
+                }
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable() => {
+                if let Some(index) = self.prg_ram_address(self.prg_banks[0], address) {
+                    self.prg_ram[index] = value;
+                }
+            }
+            0x8000..=0xDFFF if self.prg_ram_writable() => {
+                if let (false, page) = self.prg_page(address) {
+                    if let Some(index) = self.prg_ram_address(page as u8, address) {
+                        self.prg_ram[index] = value;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.track_ppu_read(address);
+
+        if let Fetch::Background(..) = self.fetch() {
+            if self.split_fetch {
+                let bank = self.split_bank as usize * 0x1000;
+                let offset = (address as usize & 0x0FF8) | (self.split_y as usize & 0b111);
+                return self.read_chr(bank + offset);
+            }
+
+            if self.exram_mode == ExRamMode::ExtendedAttribute {
+                let bank =
+                    (self.extended_attribute & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                return self.read_chr(bank * 0x1000 + (address as usize & 0x0FFF));
+            }
+        }
+
+        self.read_chr(self.chr_address(address))
+    }
+
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        self.read_chr(self.bank_address(address, false))
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let index = self.chr_address(address) % self.chr.len();
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        let mut pages = [0; 4];
+        for (quadrant, page) in pages.iter_mut().enumerate() {
+            *page = (self.nametable_mapping >> (quadrant * 2)) & 1;
+        }
+
+        Mirroring::Custom(pages)
+    }
+
+    fn read_nametable(&mut self, address: u16) -> Option<u8> {
+        self.track_ppu_read(address);
+
+        let attribute = address & 0x03FF >= 0x03C0;
+
+        if let Fetch::Background(column, line) = self.fetch() {
+            let nametable_fetch = self.fetch_index & 0b11 == 0;
+
+            if nametable_fetch {
+                self.split_fetch = self.in_split(column);
+            }
+
+            if self.split_fetch {
+                let y = (self.split_scroll as u16 + line) % 240;
+                self.split_y = y;
+
+                return Some(match nametable_fetch {
+                    true => self.exram[((y / 8) * 32 + column % 32) as usize],
+                    false => {
+                        let index = 0x03C0 + (y / 32) * 8 + (column % 32) / 4;
+                        let shift = ((y / 16) & 1) * 4 + ((column / 2) & 1) * 2;
+                        replicate_attribute(self.exram[index as usize] >> shift)
+                    }
+                });
+            }
+
+            if self.exram_mode == ExRamMode::ExtendedAttribute {
+                match nametable_fetch {
+                    true => self.extended_attribute = self.exram[(address & 0x03FF) as usize],
+                    false => return Some(replicate_attribute(self.extended_attribute >> 6)),
+                }
+            }
+        }
+
+        match self.nametable_source(address) {
+            2 => Some(match self.exram_mode {
+                ExRamMode::Nametable | ExRamMode::ExtendedAttribute => {
+                    self.exram[(address & 0x03FF) as usize]
+                }
+                _ => 0,
+            }),
+            3 => Some(match attribute {
+                true => replicate_attribute(self.fill_attribute),
+                false => self.fill_tile,
+            }),
+            _ => None,
+        }
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8) -> bool {
+        match self.nametable_source(address) {
+            2 => {
+                if let ExRamMode::Nametable | ExRamMode::ExtendedAttribute = self.exram_mode {
+                    self.exram[(address & 0x03FF) as usize] = value;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn ppu_register_write(&mut self, address: u16, value: u8) {
+        match address & 0x2007 {
+            0x2000 => self.large_sprites = value & 0b0010_0000 != 0,
+            0x2001 => {
+                self.rendering_enabled = value & 0b0001_1000 != 0;
+                if !self.rendering_enabled {
+                    self.leave_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.ppu_idle > 0 {
+            self.ppu_idle -= 1;
+            if self.ppu_idle == 0 {
+                self.leave_frame();
+            }
+        }
+
+        self.clock_audio();
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_enabled && self.irq_pending) || (self.pcm.irq_enabled && self.pcm.irq_pending)
+    }
+
+    fn audio_output(&self) -> f32 {
+        let pulse = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let pulse_out = match pulse as u8 {
+            0 => 0.0,
+            _ => 95.88 / (8128.0 / pulse + 100.0),
+        };
+
+        pulse_out + self.pcm.output as f32 * 0.002
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match self.battery {
+            true => Some(self.prg_ram.clone()),
+            false => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = std::cmp::min(data.len(), self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod mmc5_test {
+    use super::*;
+
+    fn mmc5() -> Mmc5 {
+        let mut prg_rom = vec![0; 8 * PRG_PAGE_SIZE];
+        for (page, chunk) in prg_rom.chunks_mut(PRG_PAGE_SIZE).enumerate() {
+            chunk[0] = page as u8;
+        }
+
+        let mut rom = Rom::test(5, 0, prg_rom, vec![0; 0x8000]);
+        rom.mirroring = Mirroring::Horizontal;
+        rom.prg_ram_size = 0x10000;
+
+        Mmc5::new(&rom)
+    }
+
+    //Эмулирует выборки PPU на одной видимой строке
+    fn render_scanline(mmc5: &mut Mmc5) {
+        for tile in 0..32 {
+            mmc5.read_nametable(0x2002 + tile);
+            mmc5.read_nametable(0x23C0);
+            mmc5.ppu_read(0x0000);
+            mmc5.ppu_read(0x0008);
+        }
+        for _ in 0..8 {
+            mmc5.read_nametable(0x2000);
+            mmc5.read_nametable(0x2000);
+            mmc5.ppu_read(0x1000);
+            mmc5.ppu_read(0x1008);
+        }
+        for tile in 0..2 {
+            mmc5.read_nametable(0x2000 + tile);
+            mmc5.read_nametable(0x23C0);
+            mmc5.ppu_read(0x0000);
+            mmc5.ppu_read(0x0008);
+        }
+        mmc5.read_nametable(0x2002);
+        mmc5.read_nametable(0x2002);
+    }
+
+    #[test]
+    fn test_prg_mode_3() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5114, 0x81);
+        mmc5.cpu_write(0x5115, 0x82);
+        mmc5.cpu_write(0x5116, 0x83);
+
+        assert_eq!(mmc5.cpu_read(0x8000), Some(1));
+        assert_eq!(mmc5.cpu_read(0xA000), Some(2));
+        assert_eq!(mmc5.cpu_read(0xC000), Some(3));
+        assert_eq!(mmc5.cpu_read(0xE000), Some(7));
+    }
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x6000, 0x42);
+        assert_eq!(mmc5.cpu_read(0x6000), Some(0));
+
+        mmc5.cpu_write(0x5102, 0b10);
+        mmc5.cpu_write(0x5103, 0b01);
+        mmc5.cpu_write(0x6000, 0x42);
+        assert_eq!(mmc5.cpu_read(0x6000), Some(0x42));
+    }
+
+    #[test]
+    fn test_multiplier() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5205, 200);
+        mmc5.cpu_write(0x5206, 150);
+
+        assert_eq!(mmc5.cpu_read(0x5205), Some(0x30));
+        assert_eq!(mmc5.cpu_read(0x5206), Some(0x75));
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5203, 2);
+        mmc5.cpu_write(0x5204, 0x80);
+
+        render_scanline(&mut mmc5);
+        render_scanline(&mut mmc5);
+        assert!(mmc5.in_frame);
+        assert!(!mmc5.irq());
+
+        render_scanline(&mut mmc5);
+        render_scanline(&mut mmc5);
+        assert!(mmc5.irq());
+
+        assert_eq!(mmc5.cpu_read(0x5204), Some(0b1100_0000));
+        assert!(!mmc5.irq());
+
+        mmc5.cpu_read(0xFFFA);
+        assert_eq!(mmc5.cpu_read(0x5204), Some(0));
+    }
+
+    #[test]
+    fn test_pcm_irq() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5010, 0b1000_0000);
+        mmc5.cpu_write(0x5011, 0x40);
+        mmc5.cpu_write(0x5011, 0);
+        assert_eq!(mmc5.pcm.output, 0x40);
+        assert!(!mmc5.irq());
+
+        //В режиме чтения прерывание вызывает прочитанный из $8000-$BFFF ноль
+        mmc5.cpu_write(0x5010, 0b1000_0001);
+        mmc5.cpu_write(0x5114, 0x80);
+        assert_eq!(mmc5.cpu_read(0x8000), Some(0));
+        assert!(mmc5.irq());
+        assert_eq!(mmc5.cpu_read(0x5010), Some(0b1000_0001));
+        assert!(!mmc5.irq());
+    }
+
+    #[test]
+    fn test_fill_mode() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5105, 0b1111_1111);
+        mmc5.cpu_write(0x5106, 0x24);
+        mmc5.cpu_write(0x5107, 0b10);
+
+        assert_eq!(mmc5.read_nametable(0x2400), Some(0x24));
+        assert_eq!(mmc5.read_nametable(0x27C0), Some(0b1010_1010));
+    }
+}
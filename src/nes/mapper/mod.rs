@@ -0,0 +1,97 @@
+pub mod bandai;
+mod eeprom;
+pub mod fme7;
+pub mod mmc2;
+pub mod mmc5;
+pub mod namco163;
+pub mod registry;
+pub mod unrom512;
+pub mod vrc4;
+pub mod vrc6;
+mod vrc_irq;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenA,
+    SingleScreenB,
+    //2 KiB дополнительной памяти на картридже
+    FourScreen,
+    //Страница CIRAM для каждой из четырех таблиц, задается маппером
+    Custom([u8; 4]),
+}
+
+impl Mirroring {
+    //Страница 1 KiB для таблицы 0-3: 0-1 - CIRAM, 2-3 - память картриджа
+    pub fn page(&self, table: usize) -> usize {
+        match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical => table & 1,
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
+            Mirroring::FourScreen => table,
+            Mirroring::Custom(pages) => (pages[table] & 1) as usize,
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub trait Mapper {
+    //$4020-$FFFF. None - картридж не выставляет данные на шину
+    fn cpu_read(&mut self, address: u16) -> Option<u8>;
+    fn cpu_write(&mut self, address: u16, value: u8);
+
+    //То же чтение, но с текущим значением открытой шины для картриджей,
+    //которые выставляют на шину только часть битов
+    fn cpu_read_open_bus(&mut self, address: u16, _open_bus: u8) -> Option<u8> {
+        self.cpu_read(address)
+    }
+
+    //$0000-$1FFF
+    fn ppu_read(&mut self, address: u16) -> u8;
+    fn ppu_write(&mut self, address: u16, value: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    //Чтение CHR спрайтов без побочных эффектов (защелки MMC2, счетчик выборок MMC5)
+    //для выборок, которых не делает настоящий PPU
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        self.ppu_read(address)
+    }
+
+    //$0000-$1FFF. Страница CIRAM вместо CHR (Namco 163), None - читаем CHR маппера
+    fn pattern_ciram_page(&self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    //$2000-$2FFF. Маппер может подменить таблицу имен, None - читаем CIRAM
+    fn read_nametable(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    //true - запись обработана маппером и в CIRAM не попадает
+    fn write_nametable(&mut self, _address: u16, _value: u8) -> bool {
+        false
+    }
+
+    //Картридж видит запись CPU в регистры PPU ($2000-$2007)
+    fn ppu_register_write(&mut self, _address: u16, _value: u8) {}
+
+    fn cpu_clock(&mut self) {}
+
+    fn irq(&self) -> bool {
+        false
+    }
+
+    //Выход звуковых каналов картриджа
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_save_data(&mut self, _data: &[u8]) {}
+}
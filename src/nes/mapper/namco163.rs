@@ -0,0 +1,379 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_PAGE_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x0400;
+const SOUND_RAM_SIZE: usize = 0x80;
+
+//Страницы CHR с номерами $E0-$FF указывают на CIRAM
+const CIRAM_PAGES: u8 = 0xE0;
+
+const IRQ_COUNTER_MAX: u16 = 0x7FFF;
+
+//Каждые 15 тактов CPU обновляется один канал
+const CHANNEL_UPDATE_PERIOD: u8 = 15;
+const CHANNELS_REGISTERS: usize = 0x40;
+const AUDIO_SCALE: f32 = 0.0018;
+
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_rom: Vec<u8>,
+    battery: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    //Запрет CIRAM в $0000-$0FFF и $1000-$1FFF
+    ciram_disabled: [bool; 2],
+    prg_ram_protect: u8,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    sound_ram: [u8; SOUND_RAM_SIZE],
+    sound_address: u8,
+    sound_auto_increment: bool,
+    sound_disabled: bool,
+    sound_timer: u8,
+    current_channel: usize,
+    outputs: [i16; 8],
+}
+
+#[allow(dead_code)]
+impl Namco163 {
+    pub fn new(rom: &Rom) -> Self {
+        Namco163 {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_rom: rom.chr_rom.clone(),
+            battery: rom.battery,
+
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [CIRAM_PAGES; 4],
+            ciram_disabled: [false; 2],
+            prg_ram_protect: 0,
+
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+
+            sound_ram: [0; SOUND_RAM_SIZE],
+            sound_address: 0,
+            sound_auto_increment: false,
+            sound_disabled: false,
+            sound_timer: 0,
+            current_channel: 7,
+            outputs: [0; 8],
+        }
+    }
+
+    fn read_prg(&self, page: usize, address: u16) -> u8 {
+        let index = page * PRG_PAGE_SIZE + (address as usize & 0x1FFF);
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    fn read_chr_page(&self, page: u8, address: u16) -> u8 {
+        match self.chr_rom.len() {
+            0 => 0,
+            len => {
+                self.chr_rom[(page as usize * CHR_PAGE_SIZE + (address as usize & 0x03FF)) % len]
+            }
+        }
+    }
+
+    fn prg_ram_writable(&self, address: u16) -> bool {
+        let page = (address - 0x6000) / 0x0800;
+        self.prg_ram_protect & 0xF0 == 0x40 && self.prg_ram_protect & (1 << page) == 0
+    }
+
+    fn sound_port(&mut self) -> usize {
+        let address = self.sound_address as usize;
+
+        if self.sound_auto_increment {
+            self.sound_address = (self.sound_address + 1) & 0x7F;
+        }
+
+        address
+    }
+
+    fn enabled_channels(&self) -> usize {
+        ((self.sound_ram[0x7F] >> 4) & 0b111) as usize + 1
+    }
+
+    fn update_channel(&mut self, channel: usize) {
+        let registers = CHANNELS_REGISTERS + channel * 8;
+        let ram = &self.sound_ram[registers..registers + 8];
+
+        let frequency = ram[0] as u32 | (ram[2] as u32) << 8 | (ram[4] as u32 & 0b11) << 16;
+        let mut phase = ram[1] as u32 | (ram[3] as u32) << 8 | (ram[5] as u32) << 16;
+        let length = 256 - (ram[4] & 0xFC) as u32;
+        let offset = ram[6] as u32;
+        let volume = (ram[7] & 0x0F) as i16;
+
+        phase = (phase + frequency) % (length << 16);
+
+        let sample_address = (((phase >> 16) + offset) & 0xFF) as usize;
+        let sample = (self.sound_ram[sample_address / 2] >> ((sample_address & 1) * 4)) & 0x0F;
+
+        self.sound_ram[registers + 1] = phase as u8;
+        self.sound_ram[registers + 3] = (phase >> 8) as u8;
+        self.sound_ram[registers + 5] = (phase >> 16) as u8;
+
+        self.outputs[channel] = (sample as i16 - 8) * volume;
+    }
+
+    fn clock_audio(&mut self) {
+        if self.sound_disabled {
+            return;
+        }
+
+        self.sound_timer += 1;
+        if self.sound_timer < CHANNEL_UPDATE_PERIOD {
+            return;
+        }
+        self.sound_timer = 0;
+
+        self.update_channel(self.current_channel);
+
+        //Каналы обновляются по кругу с 7 по 8 - N
+        let last_channel = 8 - self.enabled_channels();
+        self.current_channel = match self.current_channel {
+            channel if channel <= last_channel => 7,
+            channel => channel - 1,
+        };
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x4800..=0x4FFF => {
+                let index = self.sound_port();
+                Some(self.sound_ram[index])
+            }
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => Some((self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+            0x6000..=0x7FFF => Some(self.prg_ram[(address - 0x6000) as usize]),
+            0x8000..=0x9FFF => Some(self.read_prg(self.prg_banks[0] as usize, address)),
+            0xA000..=0xBFFF => Some(self.read_prg(self.prg_banks[1] as usize, address)),
+            0xC000..=0xDFFF => Some(self.read_prg(self.prg_banks[2] as usize, address)),
+            0xE000..=0xFFFF => {
+                let last_page = self.prg_rom.len() / PRG_PAGE_SIZE - 1;
+                Some(self.read_prg(last_page, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4800..=0x4FFF => {
+                let index = self.sound_port();
+                self.sound_ram[index] = value;
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((value as u16 & 0x7F) << 8);
+                self.irq_enabled = value & 0b1000_0000 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(address) => {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            0x8000..=0xBFFF => self.chr_banks[((address - 0x8000) / 0x0800) as usize] = value,
+            0xC000..=0xDFFF => {
+                self.nametable_banks[((address - 0xC000) / 0x0800) as usize] = value;
+            }
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = value & 0x3F;
+                self.sound_disabled = value & 0b0100_0000 != 0;
+            }
+            0xE800..=0xEFFF => {
+                self.prg_banks[1] = value & 0x3F;
+                self.ciram_disabled = [value & 0b0100_0000 != 0, value & 0b1000_0000 != 0];
+            }
+            0xF000..=0xF7FF => self.prg_banks[2] = value & 0x3F,
+            0xF800..=0xFFFF => {
+                self.prg_ram_protect = value;
+                self.sound_address = value & 0x7F;
+                self.sound_auto_increment = value & 0b1000_0000 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.read_chr_page(self.chr_banks[(address as usize) / CHR_PAGE_SIZE], address)
+    }
+
+    fn ppu_write(&mut self, _address: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        let mut pages = [0; 4];
+        for (page, bank) in pages.iter_mut().zip(self.nametable_banks.iter()) {
+            *page = bank & 1;
+        }
+
+        Mirroring::Custom(pages)
+    }
+
+    fn pattern_ciram_page(&self, address: u16) -> Option<u8> {
+        let page = self.chr_banks[(address as usize) / CHR_PAGE_SIZE];
+
+        match page >= CIRAM_PAGES && !self.ciram_disabled[(address >> 12) as usize] {
+            true => Some(page & 1),
+            false => None,
+        }
+    }
+
+    fn read_nametable(&mut self, address: u16) -> Option<u8> {
+        match self.nametable_banks[((address >> 10) & 0b11) as usize] {
+            page if page >= CIRAM_PAGES => None,
+            page => Some(self.read_chr_page(page, address)),
+        }
+    }
+
+    fn write_nametable(&mut self, address: u16, _value: u8) -> bool {
+        self.nametable_banks[((address >> 10) & 0b11) as usize] < CIRAM_PAGES
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq_pending = true;
+            }
+        }
+
+        self.clock_audio();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    //Чип выводит каналы по очереди, поэтому среднее значение на выходе
+    //равно сумме каналов, деленной на их количество
+    fn audio_output(&self) -> f32 {
+        let channels = self.enabled_channels();
+        let sum: i16 = self.outputs[8 - channels..].iter().sum();
+
+        sum as f32 / channels as f32 * AUDIO_SCALE
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match self.battery {
+            true => Some([&self.prg_ram[..], &self.sound_ram[..]].concat()),
+            false => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = std::cmp::min(data.len(), PRG_RAM_SIZE);
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+
+        if data.len() == PRG_RAM_SIZE + SOUND_RAM_SIZE {
+            self.sound_ram.copy_from_slice(&data[PRG_RAM_SIZE..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod namco163_test {
+    use super::*;
+
+    fn namco163() -> Namco163 {
+        let mut chr_rom = vec![0; 0x20000];
+        for (page, chunk) in chr_rom.chunks_mut(CHR_PAGE_SIZE).enumerate() {
+            chunk[0] = page as u8;
+        }
+
+        let mut rom = Rom::test(19, 0, vec![0; 0x20000], chr_rom);
+        rom.mirroring = Mirroring::Horizontal;
+        rom.battery = true;
+        rom.prg_nvram_size = PRG_RAM_SIZE;
+
+        Namco163::new(&rom)
+    }
+
+    #[test]
+    fn test_sound_ram_auto_increment() {
+        let mut namco163 = namco163();
+        namco163.cpu_write(0xF800, 0x80 | 0x7E);
+        namco163.cpu_write(0x4800, 1);
+        namco163.cpu_write(0x4800, 2);
+        namco163.cpu_write(0x4800, 3);
+
+        assert_eq!(namco163.sound_ram[0x7E], 1);
+        assert_eq!(namco163.sound_ram[0x7F], 2);
+        assert_eq!(namco163.sound_ram[0x00], 3);
+    }
+
+    #[test]
+    fn test_irq_counter() {
+        let mut namco163 = namco163();
+        namco163.cpu_write(0x5000, 0xFD);
+        namco163.cpu_write(0x5800, 0xFF);
+
+        namco163.cpu_clock();
+        assert!(!namco163.irq());
+
+        namco163.cpu_clock();
+        assert!(namco163.irq());
+        assert_eq!(namco163.cpu_read(0x5800), Some(0xFF));
+
+        namco163.cpu_write(0x5800, 0xFF);
+        assert!(!namco163.irq());
+    }
+
+    #[test]
+    fn test_rom_nametable() {
+        let mut namco163 = namco163();
+        namco163.cpu_write(0xC800, 0x12);
+        namco163.cpu_write(0xD000, 0xE1);
+
+        assert_eq!(namco163.read_nametable(0x2400), Some(0x12));
+        assert_eq!(namco163.read_nametable(0x2800), None);
+        assert_eq!(namco163.mirroring(), Mirroring::Custom([0, 0, 1, 0]));
+    }
+
+    #[test]
+    fn test_ciram_patterns() {
+        let mut namco163 = namco163();
+        namco163.cpu_write(0x8000, 0xE1);
+        namco163.cpu_write(0xA000, 0xE0);
+
+        assert_eq!(namco163.pattern_ciram_page(0x0000), Some(1));
+        assert_eq!(namco163.pattern_ciram_page(0x1000), Some(0));
+
+        //Бит 7 $E800 запрещает CIRAM в $1000-$1FFF
+        namco163.cpu_write(0xE800, 0x80);
+        assert_eq!(namco163.pattern_ciram_page(0x1000), None);
+
+        //Бит 6 - в $0000-$0FFF, там читается страница $E1 по модулю 128 KiB CHR ROM
+        namco163.cpu_write(0xE800, 0x40);
+        assert_eq!(namco163.pattern_ciram_page(0x0000), None);
+        assert_eq!(namco163.pattern_ciram_page(0x1000), Some(0));
+        assert_eq!(namco163.ppu_read(0x0000), 0x61);
+    }
+
+    #[test]
+    fn test_save_includes_sound_ram() {
+        let mut original = namco163();
+        original.sound_ram[5] = 0x42;
+
+        let save = original.save_data().unwrap();
+        assert_eq!(save.len(), PRG_RAM_SIZE + SOUND_RAM_SIZE);
+
+        let mut restored = namco163();
+        restored.load_save_data(&save);
+        assert_eq!(restored.sound_ram[5], 0x42);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::bandai::Bandai;
+use crate::nes::mapper::fme7::Fme7;
+use crate::nes::mapper::mmc2::Mmc2;
+use crate::nes::mapper::mmc5::Mmc5;
+use crate::nes::mapper::namco163::Namco163;
+use crate::nes::mapper::unrom512::Unrom512;
+use crate::nes::mapper::vrc4::Vrc4;
+use crate::nes::mapper::vrc6::Vrc6;
+use crate::nes::mapper::Mapper;
+
+pub type MapperConstructor = fn(&Rom) -> Box<dyn Mapper>;
+
+//Таблица плат: номер маппера (с сабмаппером или без) или имя платы UNIF -> конструктор.
+//Сторонний код может добавить свои платы до загрузки картриджа
+pub struct MapperRegistry {
+    numbered: HashMap<(u16, Option<u8>), MapperConstructor>,
+    named: HashMap<String, MapperConstructor>,
+}
+
+#[allow(dead_code)]
+impl MapperRegistry {
+    pub fn new() -> Self {
+        MapperRegistry {
+            numbered: HashMap::new(),
+            named: HashMap::new(),
+        }
+    }
+
+    //Платы, поддерживаемые эмулятором
+    pub fn with_builtin() -> Self {
+        let mut registry = MapperRegistry::new();
+
+        registry.register(5, |rom| Box::new(Mmc5::new(rom)));
+        registry.register(9, |rom| Box::new(Mmc2::new(rom)));
+        registry.register(10, |rom| Box::new(Mmc2::new(rom)));
+        registry.register(19, |rom| Box::new(Namco163::new(rom)));
+        registry.register(30, |rom| Box::new(Unrom512::new(rom)));
+        registry.register(69, |rom| Box::new(Fme7::new(rom)));
+
+        for &mapper in [16, 153, 157, 159].iter() {
+            registry.register(mapper, |rom| Box::new(Bandai::new(rom)));
+        }
+        for &mapper in [21, 22, 23, 25].iter() {
+            registry.register(mapper, |rom| Box::new(Vrc4::new(rom)));
+        }
+        for &mapper in [24, 26].iter() {
+            registry.register(mapper, |rom| Box::new(Vrc6::new(rom)));
+        }
+
+        for &board in ["ELROM", "EKROM", "ETROM", "EWROM"].iter() {
+            registry.register_board(board, |rom| Box::new(Mmc5::new(rom)));
+        }
+        for &board in ["UNROM-512-8", "UNROM-512-16", "UNROM-512-32"].iter() {
+            registry.register_board(board, |rom| Box::new(Unrom512::new(rom)));
+        }
+
+        registry
+    }
+
+    //Конструктор для всех сабмапперов, если точного совпадения нет
+    pub fn register(&mut self, mapper: u16, constructor: MapperConstructor) {
+        self.numbered.insert((mapper, None), constructor);
+    }
+
+    pub fn register_submapper(
+        &mut self,
+        mapper: u16,
+        submapper: u8,
+        constructor: MapperConstructor,
+    ) {
+        self.numbered.insert((mapper, Some(submapper)), constructor);
+    }
+
+    pub fn register_board(&mut self, board: &str, constructor: MapperConstructor) {
+        self.named.insert(board.to_uppercase(), constructor);
+    }
+
+    pub fn create(&self, rom: &Rom) -> Result<Box<dyn Mapper>, String> {
+        let constructor = match &rom.board {
+            Some(board) => self
+                .named
+                .get(&board.to_uppercase())
+                .ok_or_else(|| format!("Board {} unsupported", board))?,
+            None => self
+                .numbered
+                .get(&(rom.mapper, Some(rom.submapper)))
+                .or_else(|| self.numbered.get(&(rom.mapper, None)))
+                .ok_or_else(|| format!("Mapper {} unsupported", rom.mapper))?,
+        };
+
+        Ok(constructor(rom))
+    }
+}
+
+impl Default for MapperRegistry {
+    fn default() -> Self {
+        MapperRegistry::with_builtin()
+    }
+}
+
+#[cfg(test)]
+mod registry_test {
+    use super::*;
+    use crate::nes::mapper::Mirroring;
+
+    struct Dummy;
+
+    impl Mapper for Dummy {
+        fn cpu_read(&mut self, _address: u16) -> Option<u8> {
+            Some(0x42)
+        }
+
+        fn cpu_write(&mut self, _address: u16, _value: u8) {}
+
+        fn ppu_read(&mut self, _address: u16) -> u8 {
+            0
+        }
+
+        fn ppu_write(&mut self, _address: u16, _value: u8) {}
+
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+    }
+
+    fn rom(mapper: u16, submapper: u8, board: Option<&str>) -> Rom {
+        let mut rom = Rom::test(mapper, submapper, vec![0; 0x8000], vec![0; 0x2000]);
+        rom.board = board.map(|board| board.to_string());
+
+        rom
+    }
+
+    #[test]
+    fn test_submapper_overrides_mapper() {
+        let mut registry = MapperRegistry::default();
+        registry.register_submapper(23, 7, |_| Box::new(Dummy));
+
+        let mut mapper = registry.create(&rom(23, 7, None)).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000), Some(0x42));
+
+        let mut mapper = registry.create(&rom(23, 1, None)).unwrap();
+        assert_eq!(mapper.cpu_read(0x8000), Some(0));
+    }
+
+    #[test]
+    fn test_unif_board() {
+        let mut registry = MapperRegistry::new();
+        assert!(registry.create(&rom(0, 0, Some("PROTO-1"))).is_err());
+
+        registry.register_board("proto-1", |_| Box::new(Dummy));
+        assert!(registry.create(&rom(0, 0, Some("PROTO-1"))).is_ok());
+        assert!(registry.create(&rom(5, 0, None)).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_PAGE_SIZE: usize = 0x4000;
+const CHR_PAGE_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x8000;
+
+//SST39SF040 стирается секторами по 4 KiB
+const SECTOR_SIZE: usize = 0x1000;
+const MANUFACTURER_ID: u8 = 0xBF;
+const DEVICE_ID: u8 = 0xB7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FlashState {
+    Ready,
+    //Последовательность разблокировки: $AA по $5555, $55 по $2AAA, команда по $5555
+    Unlock,
+    Command,
+    Program,
+    Erase,
+    EraseUnlock,
+    EraseCommand,
+    SoftwareId,
+}
+
+//UNROM 512 (маппер 30) с перезаписываемой программой на флеше SST39SF040
+pub struct Unrom512 {
+    prg_rom: Vec<u8>,
+    chr_ram: Vec<u8>,
+    //Бит батарейки в заголовке означает, что плата умеет писать во флеш
+    flashable: bool,
+    //Биты 3 и 0 байта 6 заголовка: %1000 - управляемый одноэкранный режим,
+    //%1001 - четыре экрана
+    one_screen: bool,
+    header_mirroring: Mirroring,
+
+    prg_bank: u8,
+    chr_bank: u8,
+    screen: u8,
+
+    flash_state: FlashState,
+    dirty_sectors: Vec<bool>,
+}
+
+#[allow(dead_code)]
+impl Unrom512 {
+    pub fn new(rom: &Rom) -> Self {
+        Unrom512 {
+            prg_rom: rom.prg_rom.clone(),
+            chr_ram: vec![0; CHR_RAM_SIZE],
+            flashable: rom.battery,
+            one_screen: rom.mirroring == Mirroring::FourScreen && !rom.mirroring_bit,
+            header_mirroring: rom.mirroring,
+
+            prg_bank: 0,
+            chr_bank: 0,
+            screen: 0,
+
+            flash_state: FlashState::Ready,
+            dirty_sectors: vec![false; rom.prg_rom.len() / SECTOR_SIZE],
+        }
+    }
+
+    fn flash_address(&self, page: usize, address: u16) -> usize {
+        (page * PRG_PAGE_SIZE + (address as usize & 0x3FFF)) % self.prg_rom.len()
+    }
+
+    fn write_register(&mut self, value: u8) {
+        self.prg_bank = value & 0x1F;
+        self.chr_bank = (value >> 5) & 0b11;
+        self.screen = value >> 7;
+    }
+
+    //Команды принимаются по адресам $5555 и $2AAA внутри микросхемы
+    fn write_flash(&mut self, address: u16, value: u8) {
+        let flash_address = self.flash_address(self.prg_bank as usize, address);
+        let command_address = flash_address & 0x7FFF;
+
+        self.flash_state = match (self.flash_state, command_address, value) {
+            (FlashState::Program, _, _) => {
+                //Программирование может только сбрасывать биты
+                self.prg_rom[flash_address] &= value;
+                self.dirty_sectors[flash_address / SECTOR_SIZE] = true;
+                FlashState::Ready
+            }
+            (FlashState::EraseCommand, _, 0x30) => {
+                let sector = flash_address / SECTOR_SIZE;
+                let start = sector * SECTOR_SIZE;
+                self.prg_rom[start..start + SECTOR_SIZE]
+                    .iter_mut()
+                    .for_each(|byte| *byte = 0xFF);
+                self.dirty_sectors[sector] = true;
+                FlashState::Ready
+            }
+            (FlashState::EraseCommand, 0x5555, 0x10) => {
+                self.prg_rom.iter_mut().for_each(|byte| *byte = 0xFF);
+                self.dirty_sectors
+                    .iter_mut()
+                    .for_each(|dirty| *dirty = true);
+                FlashState::Ready
+            }
+            (_, _, 0xF0) => FlashState::Ready,
+            (FlashState::Ready, 0x5555, 0xAA) => FlashState::Unlock,
+            (FlashState::SoftwareId, 0x5555, 0xAA) => FlashState::Unlock,
+            (FlashState::Unlock, 0x2AAA, 0x55) => FlashState::Command,
+            (FlashState::Command, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Command, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Command, 0x5555, 0x90) => FlashState::SoftwareId,
+            (FlashState::Erase, 0x5555, 0xAA) => FlashState::EraseUnlock,
+            (FlashState::EraseUnlock, 0x2AAA, 0x55) => FlashState::EraseCommand,
+            (FlashState::SoftwareId, _, _) => FlashState::SoftwareId,
+            _ => FlashState::Ready,
+        };
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        let page = match address {
+            0x8000..=0xBFFF => self.prg_bank as usize,
+            0xC000..=0xFFFF => self.prg_rom.len() / PRG_PAGE_SIZE - 1,
+            _ => return None,
+        };
+
+        let flash_address = self.flash_address(page, address);
+
+        match self.flash_state {
+            FlashState::SoftwareId => match flash_address & 1 {
+                0 => Some(MANUFACTURER_ID),
+                _ => Some(DEVICE_ID),
+            },
+            _ => Some(self.prg_rom[flash_address]),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match (address, self.flashable) {
+            (0x8000..=0xBFFF, true) => self.write_flash(address, value),
+            (0x8000..=0xFFFF, _) => self.write_register(value),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr_ram[self.chr_bank as usize * CHR_PAGE_SIZE + address as usize]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        self.chr_ram[self.chr_bank as usize * CHR_PAGE_SIZE + address as usize] = value;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.one_screen, self.screen) {
+            (true, 0) => Mirroring::SingleScreenA,
+            (true, _) => Mirroring::SingleScreenB,
+            (false, _) => self.header_mirroring,
+        }
+    }
+
+    //Сохраняем только измененные секторы: номер (2 байта LE) и содержимое
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.flashable {
+            return None;
+        }
+
+        let mut data = vec![];
+        for (sector, _) in self.dirty_sectors.iter().enumerate().filter(|(_, &d)| d) {
+            let start = sector * SECTOR_SIZE;
+            data.extend_from_slice(&(sector as u16).to_le_bytes());
+            data.extend_from_slice(&self.prg_rom[start..start + SECTOR_SIZE]);
+        }
+
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        for record in data.chunks_exact(2 + SECTOR_SIZE) {
+            let sector = u16::from_le_bytes([record[0], record[1]]) as usize;
+            if sector >= self.dirty_sectors.len() {
+                continue;
+            }
+
+            let start = sector * SECTOR_SIZE;
+            self.prg_rom[start..start + SECTOR_SIZE].copy_from_slice(&record[2..]);
+            self.dirty_sectors[sector] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod unrom512_test {
+    use super::*;
+
+    fn board(battery: bool) -> Unrom512 {
+        let mut rom = Rom::test(30, 0, vec![0xFF; 0x80000], vec![]);
+        rom.mirroring = Mirroring::FourScreen;
+        rom.battery = battery;
+
+        Unrom512::new(&rom)
+    }
+
+    fn command(unrom: &mut Unrom512, value: u8) {
+        unrom.cpu_write(0xC000, 1);
+        unrom.cpu_write(0x9555, 0xAA);
+        unrom.cpu_write(0xC000, 0);
+        unrom.cpu_write(0xAAAA, 0x55);
+        unrom.cpu_write(0xC000, 1);
+        unrom.cpu_write(0x9555, value);
+    }
+
+    #[test]
+    fn test_banking_and_mirroring() {
+        let mut unrom = board(false);
+        unrom.cpu_write(0x8000, 0b1100_0011);
+        assert_eq!(unrom.prg_bank, 3);
+        assert_eq!(unrom.mirroring(), Mirroring::SingleScreenB);
+
+        unrom.ppu_write(0x0010, 42);
+        unrom.cpu_write(0x8000, 0);
+        assert_eq!(unrom.ppu_read(0x0010), 0);
+        assert_eq!(unrom.mirroring(), Mirroring::SingleScreenA);
+
+        //Сырой бит 0 задают только образы с четырьмя экранами
+        let mut rom = Rom::test(30, 0, vec![0xFF; 0x80000], vec![]);
+        rom.mirroring = Mirroring::FourScreen;
+        rom.mirroring_bit = true;
+        let mut unrom = Unrom512::new(&rom);
+        unrom.cpu_write(0x8000, 0x80);
+        assert_eq!(unrom.mirroring(), Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn test_header_mirroring() {
+        //Маппер 30, 512 KiB PRG ROM, без CHR ROM
+        let header = |flags_6: u8| {
+            let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 32, 0, 0xE0 | flags_6, 0x10];
+            data.resize(16 + 0x80000, 0);
+            Unrom512::new(&Rom::from_bytes(&data).unwrap())
+        };
+
+        assert_eq!(header(0b0000).mirroring(), Mirroring::Horizontal);
+        assert_eq!(header(0b0001).mirroring(), Mirroring::Vertical);
+        assert_eq!(header(0b1001).mirroring(), Mirroring::FourScreen);
+
+        let mut unrom = header(0b1000);
+        assert_eq!(unrom.mirroring(), Mirroring::SingleScreenA);
+        unrom.cpu_write(0x8000, 0x80);
+        assert_eq!(unrom.mirroring(), Mirroring::SingleScreenB);
+
+        //В остальных режимах бит 7 регистра на зеркалирование не влияет
+        let mut unrom = header(0b1001);
+        unrom.cpu_write(0x8000, 0x80);
+        assert_eq!(unrom.mirroring(), Mirroring::FourScreen);
+        let mut unrom = header(0b0001);
+        unrom.cpu_write(0x8000, 0x80);
+        assert_eq!(unrom.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_software_id() {
+        let mut unrom = board(true);
+        command(&mut unrom, 0x90);
+        assert_eq!(unrom.cpu_read(0x8000), Some(MANUFACTURER_ID));
+        assert_eq!(unrom.cpu_read(0x8001), Some(DEVICE_ID));
+
+        unrom.cpu_write(0x8000, 0xF0);
+        assert_eq!(unrom.cpu_read(0x8000), Some(0xFF));
+    }
+
+    #[test]
+    fn test_program_erase_and_save() {
+        let mut unrom = board(true);
+        command(&mut unrom, 0xA0);
+        unrom.cpu_write(0xC000, 2);
+        unrom.cpu_write(0x8123, 0x5A);
+        assert_eq!(unrom.cpu_read(0x8123), Some(0x5A));
+
+        let save = unrom.save_data().unwrap();
+        assert_eq!(save.len(), 2 + SECTOR_SIZE);
+
+        let mut restored = board(true);
+        restored.load_save_data(&save);
+        restored.cpu_write(0xC000, 2);
+        assert_eq!(restored.cpu_read(0x8123), Some(0x5A));
+
+        command(&mut unrom, 0x80);
+        command(&mut unrom, 0x30);
+        unrom.cpu_write(0xC000, 2);
+        assert_eq!(unrom.cpu_read(0x8123), Some(0x5A));
+
+        command(&mut unrom, 0x80);
+        unrom.cpu_write(0xC000, 1);
+        unrom.cpu_write(0x9555, 0xAA);
+        unrom.cpu_write(0xC000, 0);
+        unrom.cpu_write(0xAAAA, 0x55);
+        unrom.cpu_write(0xC000, 2);
+        unrom.cpu_write(0x8000, 0x30);
+        assert_eq!(unrom.cpu_read(0x8123), Some(0xFF));
+    }
+}
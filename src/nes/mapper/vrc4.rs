@@ -0,0 +1,327 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::vrc_irq::VrcIrq;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_PAGE_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x0400;
+
+//Пара адресных линий, подключенных к A0 и A1 регистров микросхемы
+type Wiring = (u16, u16);
+
+const A0_A1: Wiring = (0b0000_0001, 0b0000_0010);
+const A1_A0: Wiring = (0b0000_0010, 0b0000_0001);
+const A1_A2: Wiring = (0b0000_0010, 0b0000_0100);
+const A2_A3: Wiring = (0b0000_0100, 0b0000_1000);
+const A3_A2: Wiring = (0b0000_1000, 0b0000_0100);
+const A6_A7: Wiring = (0b0100_0000, 0b1000_0000);
+
+//Konami VRC2 и VRC4 (мапперы 21, 22, 23, 25)
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    battery: bool,
+    vrc2: bool,
+    //VRC2a (маппер 22) не подключает младший бит номера банка CHR
+    chr_shift: u8,
+    //Без сабмаппера платы неотличимы, поэтому объединяем обе разводки
+    wirings: Vec<Wiring>,
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    //Однобитная защелка VRC2 в $6000-$6FFF на платах без WRAM
+    microwire: Option<u8>,
+    irq: VrcIrq,
+}
+
+#[allow(dead_code)]
+impl Vrc4 {
+    pub fn new(rom: &Rom) -> Self {
+        let chr_ram = rom.chr_rom.is_empty();
+        let chr = match chr_ram {
+            true => vec![0; CHR_RAM_SIZE],
+            false => rom.chr_rom.clone(),
+        };
+
+        let (vrc2, wirings) = match (rom.mapper, rom.submapper) {
+            (21, 1) => (false, vec![A1_A2]),
+            (21, 2) => (false, vec![A6_A7]),
+            (21, _) => (false, vec![A1_A2, A6_A7]),
+            (22, _) => (true, vec![A1_A0]),
+            (23, 1) => (false, vec![A0_A1]),
+            (23, 2) => (false, vec![A2_A3]),
+            (23, 3) => (true, vec![A0_A1]),
+            (23, _) => (false, vec![A0_A1, A2_A3]),
+            (25, 1) => (false, vec![A1_A0]),
+            (25, 2) => (false, vec![A3_A2]),
+            (25, 3) => (true, vec![A1_A0]),
+            (_, _) => (false, vec![A1_A0, A3_A2]),
+        };
+
+        let ram_size = rom.prg_ram_size + rom.prg_nvram_size;
+        //Платы VRC2 без батареи WRAM не имеют, на ее месте защелка
+        let prg_ram = match ram_size == 0 || (vrc2 && !rom.battery) {
+            true => vec![],
+            false => vec![0; PRG_RAM_SIZE],
+        };
+        let microwire = match vrc2 && prg_ram.is_empty() {
+            true => Some(0),
+            false => None,
+        };
+
+        Vrc4 {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram,
+            chr,
+            chr_ram,
+            battery: rom.battery,
+            vrc2,
+            chr_shift: (rom.mapper == 22) as u8,
+            wirings,
+
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            microwire,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        let lines = self.wirings.iter().fold(0, |lines, &(a0, a1)| {
+            lines | (address & a0 != 0) as u16 | ((address & a1 != 0) as u16) << 1
+        });
+
+        (address & 0xF000) | lines
+    }
+
+    fn read_prg(&self, page: usize, address: u16) -> u8 {
+        let index = page * PRG_PAGE_SIZE + (address as usize & (PRG_PAGE_SIZE - 1));
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        let bank = (self.chr_banks[address as usize / CHR_PAGE_SIZE] >> self.chr_shift) as usize;
+        (bank * CHR_PAGE_SIZE + (address as usize % CHR_PAGE_SIZE)) % self.chr.len()
+    }
+
+    //Каждый банк CHR пишется двумя полубайтами: младший по A0 = 0, старший по A0 = 1.
+    //A1 выбирает банк из пары регистра
+    fn write_chr_bank(&mut self, register: u16, value: u8) {
+        let index = (((register - 0xB000) >> 12) * 2 + ((register >> 1) & 1)) as usize;
+        let bank = self.chr_banks[index];
+
+        self.chr_banks[index] = match register & 1 {
+            0 => (bank & 0x1F0) | (value as u16 & 0x0F),
+            _ => (bank & 0x00F) | (value as u16 & 0x1F) << 4,
+        };
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x6FFF if self.microwire.is_some() => self.microwire,
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(address - 0x6000) as usize])
+            }
+            0x8000..=0xFFFF => {
+                let last_page = self.prg_rom.len() / PRG_PAGE_SIZE - 1;
+                let page = match ((address - 0x8000) / 0x2000, self.prg_swap) {
+                    (0, false) | (2, true) => self.prg_banks[0] as usize,
+                    (0, true) | (2, false) => last_page - 1,
+                    (1, _) => self.prg_banks[1] as usize,
+                    _ => last_page,
+                };
+                Some(self.read_prg(page, address))
+            }
+            _ => None,
+        }
+    }
+
+    //Защелка VRC2 выставляет на шину только бит 0, остальные биты - открытая шина
+    fn cpu_read_open_bus(&mut self, address: u16, open_bus: u8) -> Option<u8> {
+        match (address, self.microwire) {
+            (0x6000..=0x6FFF, Some(latch)) => Some(open_bus & 0xFE | latch),
+            _ => self.cpu_read(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            match self.microwire.as_mut() {
+                Some(latch) if address < 0x7000 => *latch = value & 1,
+                Some(_) => {}
+                None if !self.prg_ram.is_empty() => {
+                    self.prg_ram[(address - 0x6000) as usize] = value
+                }
+                None => {}
+            }
+            return;
+        }
+
+        let register = self.register(address);
+
+        match (register, self.vrc2) {
+            (0x8000..=0x8003, _) => self.prg_banks[0] = value & 0x1F,
+            (0x9000..=0x9003, true) => {
+                self.mirroring = match value & 1 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                }
+            }
+            (0x9000, false) => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                }
+            }
+            (0x9002, false) => self.prg_swap = value & 0b10 != 0,
+            (0xA000..=0xA003, _) => self.prg_banks[1] = value & 0x1F,
+            (0xB000..=0xEFFF, _) => self.write_chr_bank(register, value),
+            (0xF000, false) => self.irq.write_latch_low(value),
+            (0xF001, false) => self.irq.write_latch_high(value),
+            (0xF002, false) => self.irq.write_control(value),
+            (0xF003, false) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_address(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let index = self.chr_address(address);
+            self.chr[index] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match self.battery && !self.prg_ram.is_empty() {
+            true => Some(self.prg_ram.clone()),
+            false => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = std::cmp::min(data.len(), self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod vrc4_test {
+    use super::*;
+
+    fn board(mapper: u16, submapper: u8) -> Vrc4 {
+        let mut prg_rom = vec![0; 0x40000];
+        for (page, chunk) in prg_rom.chunks_mut(PRG_PAGE_SIZE).enumerate() {
+            chunk[0] = page as u8;
+        }
+        let mut chr_rom = vec![0; 0x40000];
+        for (page, chunk) in chr_rom.chunks_mut(CHR_PAGE_SIZE).enumerate() {
+            chunk[0] = page as u8;
+        }
+
+        let rom = Rom::test(mapper, submapper, prg_rom, chr_rom);
+
+        Vrc4::new(&rom)
+    }
+
+    #[test]
+    fn test_address_line_wiring() {
+        //Старший полубайт банка CHR 1: A0 = 1, A1 = 1
+        let writes = [
+            (21, 1, 0xB006),
+            (21, 2, 0xB0C0),
+            (23, 2, 0xB00C),
+            (25, 2, 0xB00C),
+        ];
+
+        for &(mapper, submapper, address) in writes.iter() {
+            let mut vrc4 = board(mapper, submapper);
+            vrc4.cpu_write(address, 0x01);
+            assert_eq!(vrc4.ppu_read(0x0400), 0x10);
+        }
+
+        //Без сабмаппера декодируются обе разводки
+        let mut vrc4 = board(21, 0);
+        vrc4.cpu_write(0xB006, 0x01);
+        vrc4.cpu_write(0xB080, 0x02);
+        assert_eq!(vrc4.ppu_read(0x0400), 0x12);
+    }
+
+    #[test]
+    fn test_prg_swap_mode() {
+        let mut vrc4 = board(23, 1);
+        vrc4.cpu_write(0x8000, 3);
+        vrc4.cpu_write(0xA000, 4);
+        assert_eq!(vrc4.cpu_read(0x8000), Some(3));
+        assert_eq!(vrc4.cpu_read(0xA000), Some(4));
+        assert_eq!(vrc4.cpu_read(0xC000), Some(30));
+        assert_eq!(vrc4.cpu_read(0xE000), Some(31));
+
+        vrc4.cpu_write(0x9002, 0b10);
+        assert_eq!(vrc4.cpu_read(0x8000), Some(30));
+        assert_eq!(vrc4.cpu_read(0xC000), Some(3));
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut vrc4 = board(25, 1);
+        vrc4.cpu_write(0xF000, 0x0E);
+        vrc4.cpu_write(0xF002, 0x0F);
+        vrc4.cpu_write(0xF001, 0b110);
+
+        vrc4.cpu_clock();
+        assert!(!vrc4.irq());
+
+        vrc4.cpu_clock();
+        assert!(vrc4.irq());
+
+        vrc4.cpu_write(0xF003, 0);
+        assert!(!vrc4.irq());
+    }
+
+    #[test]
+    fn test_vrc2() {
+        let mut vrc2 = board(22, 0);
+        vrc2.cpu_write(0xB000, 0x06);
+        assert_eq!(vrc2.ppu_read(0x0000), 0x03);
+
+        vrc2.cpu_write(0xF002, 0x0F);
+        vrc2.cpu_write(0xF001, 0b110);
+        vrc2.cpu_clock();
+        assert!(!vrc2.irq());
+
+        vrc2.cpu_write(0x6000, 0xFF);
+        assert_eq!(vrc2.cpu_read(0x6000), Some(1));
+        assert_eq!(vrc2.cpu_read_open_bus(0x6000, 0xA4), Some(0xA5));
+
+        vrc2.cpu_write(0x6FFF, 0xFE);
+        assert_eq!(vrc2.cpu_read_open_bus(0x6000, 0xA5), Some(0xA4));
+        assert_eq!(vrc2.cpu_read_open_bus(0x7000, 0xA5), None);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::nes::cartridge::Rom;
+use crate::nes::mapper::vrc_irq::VrcIrq;
+use crate::nes::mapper::{Mapper, Mirroring};
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_RAM_SIZE: usize = 0x2000;
+const CHR_PAGE_SIZE: usize = 0x0400;
+
+//Громкость 15 у пульса VRC6 примерно равна громкости пульса 2A03
+const AUDIO_SCALE: f32 = 0.0099;
+
+#[derive(Default)]
+struct Pulse {
+    ignore_duty: bool,
+    duty: u8,
+    volume: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = value & 0b1000_0000 != 0;
+                self.duty = (value >> 4) & 0b111;
+                self.volume = value & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = value & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        match self.timer {
+            0 => {
+                self.timer = self.period >> shift;
+                self.step = self.step.wrapping_sub(1) & 0x0F;
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    fn output(&self) -> u8 {
+        match self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            true => self.volume,
+            false => 0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & 0b0011_1111,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = value & 0b1000_0000 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    //Скорость прибавляется каждый второй такт, на 14-м аккумулятор сбрасывается
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period >> shift;
+        self.step += 1;
+
+        match self.step {
+            14 => {
+                self.step = 0;
+                self.accumulator = 0;
+            }
+            step if step & 1 == 0 => self.accumulator = self.accumulator.wrapping_add(self.rate),
+            _ => {}
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_ram: bool,
+    battery: bool,
+    //VRC6b (маппер 26) с переставленными линиями A0 и A1
+    swapped_lines: bool,
+
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; 8],
+    banking_control: u8,
+    irq: VrcIrq,
+
+    pulses: [Pulse; 2],
+    sawtooth: Sawtooth,
+    audio_halt: bool,
+    frequency_shift: u8,
+}
+
+#[allow(dead_code)]
+impl Vrc6 {
+    pub fn new(rom: &Rom) -> Self {
+        let chr_ram = rom.chr_rom.is_empty();
+        let chr = match chr_ram {
+            true => vec![0; CHR_RAM_SIZE],
+            false => rom.chr_rom.clone(),
+        };
+
+        Vrc6 {
+            prg_rom: rom.prg_rom.clone(),
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr,
+            chr_ram,
+            battery: rom.battery,
+            swapped_lines: rom.mapper == 26,
+
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            banking_control: 0,
+            irq: VrcIrq::default(),
+
+            pulses: [Pulse::default(), Pulse::default()],
+            sawtooth: Sawtooth::default(),
+            audio_halt: false,
+            frequency_shift: 0,
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        let address = address & 0xF003;
+
+        match self.swapped_lines {
+            true => (address & 0xF000) | (address & 0b01) << 1 | (address & 0b10) >> 1,
+            false => address,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.banking_control & 0b1000_0000 != 0
+    }
+
+    fn read_prg(&self, page: usize, size: usize, address: u16) -> u8 {
+        let index = page * size + (address as usize & (size - 1));
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    //Биты 0-1 $B003 - режим банков CHR: 0 - восемь банков по 1 KiB, 1 - четыре по 2 KiB,
+    //2 и 3 - четыре по 1 KiB снизу и два по 2 KiB сверху. Бит 5 берет A10 из адреса PPU,
+    //без него 2 KiB банк - дважды повторенная страница регистра
+    fn chr_page(&self, slot: usize) -> usize {
+        let pair = |register: u8| match self.banking_control & 0b10_0000 != 0 {
+            true => (register & 0xFE) | (slot & 1) as u8,
+            false => register,
+        };
+
+        let page = match (self.banking_control & 0b11, slot) {
+            (0, _) => self.chr_banks[slot],
+            (1, _) => pair(self.chr_banks[slot / 2]),
+            (_, 0..=3) => self.chr_banks[slot],
+            _ => pair(self.chr_banks[4 + (slot - 4) / 2]),
+        };
+
+        page as usize
+    }
+
+    fn chr_address(&self, address: u16) -> usize {
+        let page = self.chr_page(address as usize / CHR_PAGE_SIZE);
+        (page * CHR_PAGE_SIZE + (address as usize % CHR_PAGE_SIZE)) % self.chr.len()
+    }
+
+    //Бит 4 $B003 - таблицы имен из CHR: в режиме 1 регистры R4-R7,
+    //в остальных R6 и R7, разложенные по битам зеркалирования.
+    //Бит 5 к таблицам имен не применяется, этого коммерческие игры не используют
+    fn nametable_address(&self, address: u16) -> Option<usize> {
+        if self.banking_control & 0b1_0000 == 0 {
+            return None;
+        }
+
+        let table = ((address >> 10) & 0b11) as usize;
+        let page = match self.banking_control & 0b11 {
+            1 => self.chr_banks[4 + table],
+            _ => match self.mirroring() {
+                Mirroring::Vertical => self.chr_banks[6 + (table & 1)],
+                Mirroring::Horizontal => self.chr_banks[6 + (table >> 1)],
+                Mirroring::SingleScreenA => self.chr_banks[6],
+                _ => self.chr_banks[7],
+            },
+        } as usize;
+
+        Some((page * CHR_PAGE_SIZE + (address as usize % CHR_PAGE_SIZE)) % self.chr.len())
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, address: u16) -> Option<u8> {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                Some(self.prg_ram[(address - 0x6000) as usize])
+            }
+            0x8000..=0xBFFF => Some(self.read_prg(self.prg_bank_16k as usize, 0x4000, address)),
+            0xC000..=0xDFFF => Some(self.read_prg(self.prg_bank_8k as usize, 0x2000, address)),
+            0xE000..=0xFFFF => {
+                let last_page = self.prg_rom.len() / 0x2000 - 1;
+                Some(self.read_prg(last_page, 0x2000, address))
+            }
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            if self.prg_ram_enabled() {
+                self.prg_ram[(address - 0x6000) as usize] = value;
+            }
+            return;
+        }
+
+        let register = self.register(address);
+
+        match register {
+            0x8000..=0x8003 => self.prg_bank_16k = value & 0x0F,
+            0x9000..=0x9002 => self.pulses[0].write(register & 0b11, value),
+            0x9003 => {
+                self.audio_halt = value & 0b001 != 0;
+                self.frequency_shift = match value & 0b110 {
+                    0b000 => 0,
+                    0b010 => 4,
+                    _ => 8,
+                };
+            }
+            0xA000..=0xA002 => self.pulses[1].write(register & 0b11, value),
+            0xB000..=0xB002 => self.sawtooth.write(register & 0b11, value),
+            0xB003 => self.banking_control = value,
+            0xC000..=0xC003 => self.prg_bank_8k = value & 0x1F,
+            0xD000..=0xD003 => self.chr_banks[(register & 0b11) as usize] = value,
+            0xE000..=0xE003 => self.chr_banks[4 + (register & 0b11) as usize] = value,
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_address(address)]
+    }
+
+    fn ppu_write(&mut self, address: u16, value: u8) {
+        if self.chr_ram {
+            let index = self.chr_address(address);
+            self.chr[index] = value;
+        }
+    }
+
+    //Для CIRAM зеркалирование всегда берется из битов 2-3, как в режиме 0.
+    //Перестановка этих битов в режимах 1-3 не эмулируется
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking_control >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenA,
+            _ => Mirroring::SingleScreenB,
+        }
+    }
+
+    fn read_nametable(&mut self, address: u16) -> Option<u8> {
+        self.nametable_address(address).map(|index| self.chr[index])
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8) -> bool {
+        match self.nametable_address(address) {
+            Some(index) => {
+                if self.chr_ram {
+                    self.chr[index] = value;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+
+        if !self.audio_halt {
+            let shift = self.frequency_shift;
+            self.pulses.iter_mut().for_each(|pulse| pulse.clock(shift));
+            self.sawtooth.clock(shift);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        sum as f32 * AUDIO_SCALE
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        match self.battery {
+            true => Some(self.prg_ram.clone()),
+            false => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = std::cmp::min(data.len(), self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+#[cfg(test)]
+mod vrc6_test {
+    use super::*;
+
+    fn vrc6(mapper: u16) -> Vrc6 {
+        let mut chr_rom = vec![0; 0x10000];
+        for (page, chunk) in chr_rom.chunks_mut(CHR_PAGE_SIZE).enumerate() {
+            chunk[0] = page as u8;
+        }
+
+        let mut rom = Rom::test(mapper, 0, vec![0; 0x40000], chr_rom);
+        rom.prg_ram_size = PRG_RAM_SIZE;
+
+        Vrc6::new(&rom)
+    }
+
+    #[test]
+    fn test_swapped_address_lines() {
+        let mut vrc6a = vrc6(24);
+        vrc6a.cpu_write(0xD001, 10);
+        assert_eq!(vrc6a.ppu_read(0x0400), 10);
+
+        let mut vrc6b = vrc6(26);
+        vrc6b.cpu_write(0xD001, 10);
+        assert_eq!(vrc6b.ppu_read(0x0800), 10);
+    }
+
+    #[test]
+    fn test_chr_banking_modes() {
+        let mut vrc6 = vrc6(24);
+        for (register, value) in [0xD000, 0xD001, 0xD002, 0xD003, 0xE000, 0xE001]
+            .iter()
+            .zip([10, 21, 30, 41, 50, 61].iter())
+        {
+            vrc6.cpu_write(*register, *value);
+        }
+
+        vrc6.cpu_write(0xB003, 0b10_0001);
+        let pages: Vec<u8> = (0..8).map(|slot| vrc6.ppu_read(slot * 0x0400)).collect();
+        assert_eq!(pages, [10, 11, 20, 21, 30, 31, 40, 41]);
+
+        vrc6.cpu_write(0xB003, 0b00_0001);
+        let pages: Vec<u8> = (0..8).map(|slot| vrc6.ppu_read(slot * 0x0400)).collect();
+        assert_eq!(pages, [10, 10, 21, 21, 30, 30, 41, 41]);
+
+        vrc6.cpu_write(0xB003, 0b10_0010);
+        let pages: Vec<u8> = (0..8).map(|slot| vrc6.ppu_read(slot * 0x0400)).collect();
+        assert_eq!(pages, [10, 21, 30, 41, 50, 51, 60, 61]);
+    }
+
+    #[test]
+    fn test_chr_nametables() {
+        let mut vrc6 = vrc6(24);
+        for (register, value) in [0xE000, 0xE001, 0xE002, 0xE003]
+            .iter()
+            .zip([4, 5, 6, 7].iter())
+        {
+            vrc6.cpu_write(*register, *value);
+        }
+
+        vrc6.cpu_write(0xB003, 0b0000);
+        assert_eq!(vrc6.read_nametable(0x2000), None);
+        assert!(!vrc6.write_nametable(0x2000, 0));
+
+        //Режим 0, вертикальное зеркалирование
+        vrc6.cpu_write(0xB003, 0b1_0000);
+        let tables: Vec<_> = (0..4)
+            .map(|table| vrc6.read_nametable(0x2000 + table * 0x0400))
+            .collect();
+        assert_eq!(tables, [Some(6), Some(7), Some(6), Some(7)]);
+        assert!(vrc6.write_nametable(0x2000, 0));
+        assert_eq!(vrc6.read_nametable(0x2000), Some(6));
+
+        //Режим 0, горизонтальное зеркалирование
+        vrc6.cpu_write(0xB003, 0b1_0100);
+        let tables: Vec<_> = (0..4)
+            .map(|table| vrc6.read_nametable(0x2000 + table * 0x0400))
+            .collect();
+        assert_eq!(tables, [Some(6), Some(6), Some(7), Some(7)]);
+
+        vrc6.cpu_write(0xB003, 0b1_0001);
+        let tables: Vec<_> = (0..4)
+            .map(|table| vrc6.read_nametable(0x2000 + table * 0x0400))
+            .collect();
+        assert_eq!(tables, [Some(4), Some(5), Some(6), Some(7)]);
+    }
+
+    #[test]
+    fn test_irq_cycle_mode() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0xF000, 0xFD);
+        vrc6.cpu_write(0xF001, 0b110);
+
+        vrc6.cpu_clock();
+        vrc6.cpu_clock();
+        assert!(!vrc6.irq());
+
+        vrc6.cpu_clock();
+        assert!(vrc6.irq());
+
+        vrc6.cpu_write(0xF002, 0);
+        assert!(!vrc6.irq());
+    }
+
+    #[test]
+    fn test_irq_scanline_mode() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0xF000, 0xFF);
+        vrc6.cpu_write(0xF001, 0b010);
+
+        for _ in 0..113 {
+            vrc6.cpu_clock();
+        }
+        assert!(!vrc6.irq());
+
+        vrc6.cpu_clock();
+        assert!(vrc6.irq());
+    }
+
+    #[test]
+    fn test_sawtooth() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0xB000, 42);
+        vrc6.cpu_write(0xB002, 0x80);
+
+        let mut outputs = vec![];
+        for _ in 0..14 {
+            vrc6.cpu_clock();
+            outputs.push(vrc6.sawtooth.output());
+        }
+
+        assert_eq!(
+            outputs,
+            [0, 5, 5, 10, 10, 15, 15, 21, 21, 26, 26, 31, 31, 0]
+        );
+    }
+}
@@ -1,15 +1,40 @@
+//Шина CPU: чтение может менять состояние устройств (регистры PPU, контроллеры)
 pub trait Memory {
-    fn read_u8(&self, address: u16) -> u8;
-    fn read_u16(&self, address: u16) -> u16;
+    fn read_u8(&mut self, address: u16) -> u8;
+    fn read_u16(&mut self, address: u16) -> u16;
 
     fn write_u8(&mut self, address: u16, value: u8);
     fn write_u16(&mut self, address: u16, value: u16);
 }
 
 pub trait Stack {
-    fn pop_u8(&mut self) -> u8;
-    fn pop_u16(&mut self) -> u16;
+    fn pop_u8(&mut self, bus: &mut dyn Memory) -> u8;
+    fn pop_u16(&mut self, bus: &mut dyn Memory) -> u16;
 
-    fn push_u8(&mut self, value: u8);
-    fn push_u16(&mut self, value: u16);
+    fn push_u8(&mut self, bus: &mut dyn Memory, value: u8);
+    fn push_u16(&mut self, bus: &mut dyn Memory, value: u16);
+}
+
+//Плоские 64 KiB без устройств: тесты и CPU::execute_commands
+impl Memory for Vec<u8> {
+    fn read_u8(&mut self, address: u16) -> u8 {
+        self[address as usize]
+    }
+
+    fn read_u16(&mut self, address: u16) -> u16 {
+        let lo = self.read_u8(address);
+        let hi = self.read_u8(address.wrapping_add(1));
+
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn write_u8(&mut self, address: u16, value: u8) {
+        self[address as usize] = value;
+    }
+
+    fn write_u16(&mut self, address: u16, value: u16) {
+        let bytes = value.to_le_bytes();
+        self.write_u8(address, bytes[0]);
+        self.write_u8(address.wrapping_add(1), bytes[1]);
+    }
 }
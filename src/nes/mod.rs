@@ -1,21 +1,608 @@
+pub mod apu;
+pub mod cartridge;
 mod cpu;
+pub mod dma;
 mod instruction;
 mod interrupt;
+pub mod mapper;
 mod mem;
+pub mod ppu;
+pub mod region;
+pub mod screenshot;
 
-use crate::nes::cpu::CPU;
+use crate::nes::apu::APU;
+use crate::nes::cartridge::Cartridge;
+use crate::nes::cpu::{CPU, INTERRUPT_CYCLES};
+use crate::nes::dma::{Dma, DmaCycle};
+use crate::nes::mem::Memory;
+use std::fs;
+use std::path::Path;
+
+use crate::nes::ppu::overscan::Overscan;
+use crate::nes::ppu::palette::Palette;
+use crate::nes::ppu::{PPU, SCREEN_WIDTH};
+use crate::nes::region::Region;
+use crate::nes::screenshot::ImageFormat;
+
+const RAM_SIZE: usize = 0x0800;
 
 #[allow(dead_code)]
 pub struct NES {
     pub cpu: CPU,
-    //pub ppu: PPU,
-    //pub ram: RAM
-    //pub apu: APU,
+    pub ppu: PPU,
+    pub ram: [u8; RAM_SIZE],
+    pub apu: APU,
+    pub cartridge: Option<Cartridge>,
+    pub dma: Dma,
+    //Вывод кадра: палитра и обрезка краев
+    pub palette: Palette,
+    pub overscan: Overscan,
+    pub cycles: u64,
+    //Последнее значение на шине данных CPU, его читают неподключенные адреса
+    open_bus: u8,
+    //Адрес последнего чтения CPU, которое повторяет остановленный ради DMC процессор.
+    //Инструкция выполняется целиком, поэтому это последнее чтение уже законченной
+    //инструкции, а не чтение того такта, на котором DMC остановил CPU
+    last_read: u16,
+    region: Region,
+    //Регион, выбранный вручную вместо заголовка ROM
+    region_override: Option<Region>,
+    //Такты мастер-генератора, еще не отданные PPU
+    ppu_clock: u32,
+    //Такты текущей инструкции, которые CPU еще выполняет
+    cpu_wait: u8,
+    //Страница, записанная в $4014: DMA ждет конца инструкции записи
+    oam_dma_page: Option<u8>,
 }
 
 #[allow(dead_code)]
 impl NES {
     pub fn new() -> Self {
-        NES { cpu: CPU::new() }
+        NES {
+            cpu: CPU::new(),
+            ppu: PPU::new(),
+            ram: [0; RAM_SIZE],
+            apu: APU::new(),
+            cartridge: None,
+            dma: Dma::new(),
+            palette: Palette::default(),
+            overscan: Overscan::default(),
+            cycles: 0,
+            open_bus: 0,
+            last_read: 0,
+            region: Region::Ntsc,
+            region_override: None,
+            ppu_clock: 0,
+            cpu_wait: 0,
+            oam_dma_page: None,
+        }
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+        self.update_region();
+        self.reset();
+    }
+
+    //Кнопка Reset: CPU читает вектор $FFFC у маппера
+    pub fn reset(&mut self) {
+        let mut cpu = std::mem::take(&mut self.cpu);
+        cpu.reset(self);
+        self.cpu = cpu;
+
+        self.cpu_wait = INTERRUPT_CYCLES - 1;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    //None - регион из заголовка ROM
+    pub fn set_region_override(&mut self, region: Option<Region>) {
+        self.region_override = region;
+        self.update_region();
+    }
+
+    fn update_region(&mut self) {
+        self.region = self
+            .region_override
+            .or_else(|| self.cartridge.as_ref().map(|cartridge| cartridge.region))
+            .unwrap_or_default();
+        self.ppu.set_region(self.region);
+        self.apu.set_region(self.region);
+    }
+
+    //Адресное пространство CPU
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        self.last_read = address;
+        self.bus_read(address)
+    }
+
+    //Чтение по шине без учета адреса CPU, его делает и DMA
+    fn bus_read(&mut self, address: u16) -> u8 {
+        //$4015 читается внутри 2A03 и не меняет значение на внешней шине
+        if address == 0x4015 {
+            return self.apu.read_status() | (self.open_bus & 0x20);
+        }
+
+        let value = match (address, self.cartridge.as_mut()) {
+            (0x0000..=0x1FFF, _) => Some(self.ram[address as usize & (RAM_SIZE - 1)]),
+            (0x2000..=0x3FFF, Some(cartridge)) => {
+                Some(self.ppu.read_register(address, cartridge.mapper.as_mut()))
+            }
+            //Порты контроллеров выставляют только младшие биты
+            (0x4016..=0x4017, _) => Some(self.open_bus & 0xE0),
+            (0x4020..=0xFFFF, Some(cartridge)) => {
+                cartridge.mapper.cpu_read_open_bus(address, self.open_bus)
+            }
+            _ => None,
+        };
+
+        self.open_bus = value.unwrap_or(self.open_bus);
+        self.open_bus
+    }
+
+    pub fn cpu_write(&mut self, address: u16, value: u8) {
+        self.open_bus = value;
+
+        match (address, self.cartridge.as_mut()) {
+            (0x0000..=0x1FFF, _) => self.ram[address as usize & (RAM_SIZE - 1)] = value,
+            (0x2000..=0x3FFF, Some(cartridge)) => {
+                self.ppu
+                    .write_register(address, value, cartridge.mapper.as_mut())
+            }
+            (0x4014, _) => self.oam_dma_page = Some(value),
+            (0x4000..=0x4013 | 0x4015 | 0x4017, _) => self.apu.write_register(address, value),
+            (0x4020..=0xFFFF, Some(cartridge)) => cartridge.mapper.cpu_write(address, value),
+            _ => {}
+        }
+    }
+
+    //CPU остановлен на время DMA
+    pub fn cpu_stalled(&self) -> bool {
+        self.dma.active()
+    }
+
+    //Один такт CPU: шаг DMA или инструкции, такт APU, точки PPU (3, у PAL в среднем 3.2)
+    //и такт картриджа
+    pub fn clock(&mut self) {
+        if !self.clock_dma() {
+            self.clock_cpu();
+        }
+        //Запись в $4014 приходится на последний такт инструкции,
+        //CPU останавливается со следующего такта
+        if self.cpu_wait == 0 {
+            if let Some(page) = self.oam_dma_page.take() {
+                self.dma.start_oam(page);
+            }
+        }
+        if let Some(value) = self.dma.take_dmc_sample() {
+            self.apu.dmc_sample(value);
+        }
+
+        self.apu.clock();
+        if let Some(address) = self.apu.dmc_fetch() {
+            self.dma.request_dmc(address);
+        }
+
+        if let Some(cartridge) = self.cartridge.as_mut() {
+            self.ppu_clock += self.region.cpu_divider();
+            while self.ppu_clock >= self.region.ppu_divider() {
+                self.ppu_clock -= self.region.ppu_divider();
+                self.ppu.tick(cartridge.mapper.as_mut());
+            }
+            cartridge.mapper.cpu_clock();
+        }
+
+        self.cycles += 1;
+    }
+
+    //Такты до конца кадра (начала vblank)
+    pub fn run_frame(&mut self) {
+        while !self.ppu.poll_frame() {
+            self.clock();
+        }
+    }
+
+    //Текущий звуковой сэмпл: 2A03 и каналы картриджа (MMC5, VRC6, Namco 163, 5B)
+    pub fn audio_sample(&self) -> f32 {
+        let expansion = self
+            .cartridge
+            .as_ref()
+            .map_or(0.0, |cartridge| cartridge.mapper.audio_output());
+
+        self.apu.output(expansion)
+    }
+
+    //Текущий кадр в RGB с учетом палитры и обрезки краев
+    pub fn frame_rgb(&self) -> Vec<u8> {
+        let rgb = self.palette.to_rgb(&self.ppu.frame_buffer);
+        self.overscan.crop(&rgb, SCREEN_WIDTH * 3)
+    }
+
+    pub fn screenshot(&self, format: ImageFormat) -> Result<Vec<u8>, String> {
+        screenshot::encode(
+            &self.frame_rgb(),
+            self.overscan.width(),
+            self.overscan.height(),
+            format,
+        )
+    }
+
+    //Формат по расширению: .ppm или PNG
+    pub fn save_screenshot<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let data = self.screenshot(ImageFormat::from_path(&path))?;
+        fs::write(path, data).map_err(|e| e.to_string())
+    }
+
+    //Инструкция выполняется целиком в первом такте, остальные такты CPU ждет.
+    //Прерывания проверяются между инструкциями, IRQ - по уровню линии.
+    //Фронт NMI защелкнут в PPU до этой проверки: чтение $2002 в гонке с vblank
+    //и сброс бита 7 $2000 еще успевают его отменить
+    fn clock_cpu(&mut self) {
+        if self.cartridge.is_none() {
+            return;
+        }
+        if self.cpu_wait > 0 {
+            self.cpu_wait -= 1;
+            return;
+        }
+        if self.cpu.jammed {
+            return;
+        }
+
+        let irq = self.apu.irq()
+            || self
+                .cartridge
+                .as_ref()
+                .is_some_and(|cartridge| cartridge.mapper.irq());
+
+        //Шина CPU - это сам NES, поэтому процессор на время инструкции вынимается
+        let mut cpu = std::mem::take(&mut self.cpu);
+        let cycles = match self.ppu.poll_nmi() {
+            true => {
+                cpu.nmi(self);
+                INTERRUPT_CYCLES
+            }
+            false if irq && cpu.irq(self) => INTERRUPT_CYCLES,
+            false => cpu.step(self),
+        };
+        self.cpu = cpu;
+
+        self.cpu_wait = cycles - 1;
+    }
+
+    //true - такт занят DMA и CPU стоит
+    fn clock_dma(&mut self) -> bool {
+        match self.dma.cycle(self.cycles & 1 == 0) {
+            DmaCycle::OamRead(address) => {
+                let value = self.bus_read(address);
+                self.dma.complete_oam_read(value);
+            }
+            DmaCycle::OamWrite(value) => self.ppu.write_oam(value),
+            DmaCycle::DmcRead(address) => {
+                let value = self.bus_read(address);
+                self.dma.complete_dmc_read(value);
+            }
+            DmaCycle::Halt => self.repeat_read(),
+            DmaCycle::Wait => {}
+            DmaCycle::Idle => return false,
+        }
+
+        true
+    }
+
+    //Повторное чтение регистров с побочным эффектом: лишний сдвиг контроллера
+    //или лишний инкремент адреса VRAM. Остальные адреса повтор не замечают.
+    //Ограничение: без потактовой модели CPU повторяется чтение из last_read, даже если
+    //на железе CPU в этот такт писал или читал другой адрес той же инструкции
+    fn repeat_read(&mut self) {
+        match self.last_read {
+            0x2000..=0x3FFF if self.last_read & 0x0007 == 0x0007 => {
+                self.bus_read(self.last_read);
+            }
+            0x4016..=0x4017 => {
+                self.bus_read(self.last_read);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for NES {
+    fn default() -> Self {
+        NES::new()
+    }
+}
+
+impl Memory for NES {
+    fn read_u8(&mut self, address: u16) -> u8 {
+        self.cpu_read(address)
+    }
+
+    fn read_u16(&mut self, address: u16) -> u16 {
+        let lo = self.cpu_read(address);
+        let hi = self.cpu_read(address.wrapping_add(1));
+
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn write_u8(&mut self, address: u16, value: u8) {
+        self.cpu_write(address, value);
+    }
+
+    fn write_u16(&mut self, address: u16, value: u16) {
+        let bytes = value.to_le_bytes();
+        self.cpu_write(address, bytes[0]);
+        self.cpu_write(address.wrapping_add(1), bytes[1]);
+    }
+}
+
+#[cfg(test)]
+mod nes_test {
+    use super::*;
+    use crate::nes::cartridge::Rom;
+
+    //UNROM 512 (маппер 30), 32 KiB PRG и CHR RAM.
+    //Программа с $C000, обработчик NMI с $E000
+    fn program_cartridge(timing: u8, program: &[u8], nmi: &[u8]) -> Cartridge {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 0, 0xE0, 0x18];
+        data.resize(16, 0);
+        data[12] = timing;
+        data.resize(16 + 0x8000, 0);
+
+        let prg = &mut data[16..];
+        prg[0x4000..0x4000 + program.len()].copy_from_slice(program);
+        prg[0x6000..0x6000 + nmi.len()].copy_from_slice(nmi);
+        prg[0x7FFA..].copy_from_slice(&[0x00, 0xE0, 0x00, 0xC0, 0x00, 0xE0]);
+
+        Cartridge::new(Rom::from_bytes(&data).unwrap()).unwrap()
+    }
+
+    //Процессор сразу после сброса зависает на KIL и не трогает шину
+    fn cartridge(timing: u8) -> Cartridge {
+        program_cartridge(timing, &[0x02], &[0x40])
+    }
+
+    #[test]
+    fn test_expansion_audio() {
+        //VRC6 (маппер 24), 32 KiB PRG и 8 KiB CHR
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 2, 1, 0x80, 0x10];
+        data.resize(16 + 0x8000 + 0x2000, 0);
+
+        let mut nes = NES::new();
+        nes.insert_cartridge(Cartridge::new(Rom::from_bytes(&data).unwrap()).unwrap());
+        let silent = nes.audio_sample();
+
+        //Пульс 1 VRC6: громкость 15 без скважности
+        nes.cpu_write(0x9000, 0x8F);
+        nes.cpu_write(0x9002, 0x80);
+        //15 * 0.0099 - шкала VRC6
+        assert!((nes.audio_sample() - silent - 0.1485).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut nes = NES::new();
+        for (index, byte) in nes.ram[0x0200..0x0300].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        nes.cycles = 0;
+
+        //Такт записи, DMA начинается после него
+        nes.cpu_write(0x4014, 0x02);
+        assert!(!nes.cpu_stalled());
+        nes.clock();
+
+        let mut stalled = 0;
+        while nes.cpu_stalled() {
+            nes.clock();
+            stalled += 1;
+        }
+
+        assert_eq!(stalled, 513);
+        assert_eq!(nes.ppu.oam[0x00], 0x00);
+        assert_eq!(nes.ppu.oam[0xFF], 0xFF);
+    }
+
+    #[test]
+    fn test_oam_dma_instruction() {
+        let program = [
+            0xA9, 0x02, //LDA #$02
+            0x8D, 0x14, 0x40, //STA $4014
+            0xEA, //NOP
+            0x02, //KIL
+        ];
+
+        let mut nes = NES::new();
+        nes.ram[0x0200..0x0300].fill(0x5A);
+        nes.insert_cartridge(program_cartridge(0, &program, &[0x40]));
+        while nes.cpu.program_counter != 0xC005 {
+            nes.clock();
+        }
+        //Такт, в котором выполнился STA
+        let start = nes.cycles - 1;
+
+        while nes.cpu.program_counter == 0xC005 {
+            nes.clock();
+        }
+        let cycles = nes.cycles - 1 - start;
+
+        //4 такта STA, затем остановка с такта после записи: 513 при нечетном такте
+        let halt = start + 4;
+        assert_eq!(cycles, 4 + 513 + (halt & 1 == 0) as u64);
+        assert_eq!(nes.ppu.oam[0xFF], 0x5A);
+    }
+
+    #[test]
+    fn test_region() {
+        let mut nes = NES::new();
+        nes.insert_cartridge(cartridge(1));
+        assert_eq!(nes.region(), Region::Pal);
+
+        //5 тактов CPU = 16 точек PPU
+        for _ in 0..5 {
+            nes.clock();
+        }
+        assert_eq!(nes.ppu.dot, 16);
+
+        nes.set_region_override(Some(Region::Dendy));
+        assert_eq!(nes.region(), Region::Dendy);
+        nes.set_region_override(None);
+        assert_eq!(nes.region(), Region::Pal);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut nes = NES::new();
+        nes.insert_cartridge(cartridge(0));
+        nes.ram[0x10] = 0x5A;
+
+        nes.cpu_read(0x0010);
+        assert_eq!(nes.cpu_read(0x4000), 0x5A);
+        assert_eq!(nes.cpu_read(0x5000), 0x5A);
+        assert_eq!(nes.cpu_read(0x4016), 0x40);
+
+        nes.cpu_write(0x0000, 0xA5);
+        assert_eq!(nes.cpu_read(0x6000), 0xA5);
+
+        //Статус APU: бит 5 с шины, сама шина не меняется
+        nes.cpu_write(0x0000, 0xFF);
+        assert_eq!(nes.cpu_read(0x4015), 0x20);
+        assert_eq!(nes.cpu_read(0x4000), 0xFF);
+    }
+
+    #[test]
+    fn test_dmc_dma() {
+        let mut nes = NES::new();
+        nes.insert_cartridge(cartridge(0));
+        while !nes.cpu.jammed {
+            nes.clock();
+        }
+
+        nes.cpu_write(0x2006, 0x20);
+        nes.cpu_write(0x2006, 0x00);
+        for value in 0..8 {
+            nes.cpu_write(0x2007, value);
+        }
+        nes.cpu_write(0x2006, 0x20);
+        nes.cpu_write(0x2006, 0x00);
+        nes.cpu_read(0x2007);
+
+        //Сэмпл из одного байта по адресу $C000, IRQ в конце
+        nes.cpu_write(0x4010, 0x80);
+        nes.cpu_write(0x4013, 0x00);
+        nes.cpu_write(0x4015, 0x10);
+        nes.clock();
+
+        let mut stalled = 0;
+        while nes.cpu_stalled() {
+            nes.clock();
+            stalled += 1;
+        }
+        assert!((3..=4).contains(&stalled));
+        assert!(nes.apu.irq());
+
+        //Каждый такт остановки, кроме чтения DMC, повторил чтение $2007
+        assert_eq!(nes.cpu_read(0x2007), stalled - 1);
+    }
+
+    #[test]
+    fn test_cpu_program() {
+        let program = [
+            0xE6, 0x10, //INC $10
+            0xA5, 0x10, //LDA $10
+            0x8D, 0x00, 0x03, //STA $0300
+            0x4C, 0x00, 0xC0, //JMP $C000
+        ];
+
+        let mut nes = NES::new();
+        nes.insert_cartridge(program_cartridge(0, &program, &[0x40]));
+        assert_eq!(nes.cpu.program_counter, 0xC000);
+
+        //Остаток сброса, затем по 5 + 3 + 4 + 3 такта на проход цикла
+        for _ in 0..INTERRUPT_CYCLES as usize - 1 + 15 * 3 {
+            nes.clock();
+        }
+
+        assert_eq!(nes.ram[0x10], 3);
+        assert_eq!(nes.ram[0x300], 3);
+        assert_eq!(nes.cpu.program_counter, 0xC000);
+    }
+
+    #[test]
+    fn test_nmi_program() {
+        //Включает NMI и крутится в цикле
+        let program = [
+            0xA9, 0x80, //LDA #$80
+            0x8D, 0x00, 0x20, //STA $2000
+            0x4C, 0x05, 0xC0, //JMP $C005
+        ];
+        //Считает кадры в $10 и пишет счетчик в фоновый цвет палитры
+        let nmi = [
+            0xE6, 0x10, //INC $10
+            0xA9, 0x3F, //LDA #$3F
+            0x8D, 0x06, 0x20, //STA $2006
+            0xA9, 0x00, //LDA #$00
+            0x8D, 0x06, 0x20, //STA $2006
+            0xA5, 0x10, //LDA $10
+            0x8D, 0x07, 0x20, //STA $2007
+            0x40, //RTI
+        ];
+
+        let mut nes = NES::new();
+        nes.insert_cartridge(program_cartridge(0, &program, &nmi));
+        for _ in 0..4 {
+            nes.run_frame();
+        }
+
+        //Кадр 4 нарисован с цветом, записанным в NMI кадра 3
+        assert_eq!(nes.ram[0x10], 3);
+        assert_eq!(nes.ppu.frame_buffer[0], 0x03);
+        assert_eq!(nes.cpu.stack_pointer, 0xFD);
+    }
+
+    #[test]
+    fn test_vblank_read_suppresses_nmi() {
+        //Включает NMI и крутится в цикле, обработчик NMI считает кадры в $10
+        let program = [
+            0xA9, 0x80, //LDA #$80
+            0x8D, 0x00, 0x20, //STA $2000
+            0x4C, 0x05, 0xC0, //JMP $C005
+        ];
+        let nmi = [0xE6, 0x10, 0x40]; //INC $10; RTI
+
+        let mut nes = NES::new();
+        nes.insert_cartridge(program_cartridge(0, &program, &nmi));
+        //Кадр в 89342 точки не делится на 3 точки такта CPU: точка 2-3 бывает не в каждом кадре
+        while nes.ppu.scanline != 241 || !(2..=3).contains(&nes.ppu.dot) {
+            nes.clock();
+        }
+        let frames = nes.ram[0x10];
+
+        //Флаг vblank уже выставлен, чтение в точке 1-2 отменяет NMI кадра
+        assert_eq!(nes.cpu_read(0x2002) & 0x80, 0x80);
+        nes.run_frame();
+        nes.run_frame();
+        assert_eq!(nes.ram[0x10], frames);
+
+        //Кадр без чтения $2002 вызывает NMI
+        nes.run_frame();
+        assert_eq!(nes.ram[0x10], frames + 1);
+    }
+
+    #[test]
+    fn test_screenshot() {
+        let mut nes = NES::new();
+        nes.overscan = Overscan::new(8, 8, 0, 0);
+        nes.ppu.frame_buffer[8 * SCREEN_WIDTH] = 0x20;
+
+        let ppm = nes.screenshot(ImageFormat::Ppm).unwrap();
+        let header = b"P6\n256 224\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 256 * 224 * 3);
+        assert_eq!(&ppm[header.len()..header.len() + 3], &nes.palette.rgb(0x20));
+
+        assert!(nes.screenshot(ImageFormat::Png).is_ok());
     }
 }
@@ -0,0 +1,1327 @@
+use crate::nes::mapper::{Mapper, Mirroring};
+use crate::nes::region::Region;
+
+pub mod ntsc;
+pub mod overscan;
+pub mod palette;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+const DOTS_PER_SCANLINE: u16 = 341;
+
+//CIRAM 2 KiB и еще 2 KiB памяти картриджа для четырех экранов
+const VRAM_SIZE: usize = 0x1000;
+const PALETTE_SIZE: usize = 0x20;
+const OAM_SIZE: usize = 0x100;
+const SECONDARY_OAM_SIZE: usize = 0x20;
+const SPRITES_PER_LINE: usize = 8;
+//Бит защелки шины PPU без обновления разряжается примерно за 600 мс
+const IO_LATCH_DECAY_FRAMES: u64 = 36;
+
+bitflags! {
+    pub struct PpuCtrl: u8 {
+        const NAMETABLE_X       = 0b00000001;
+        const NAMETABLE_Y       = 0b00000010;
+        const INCREMENT_32      = 0b00000100;
+        const SPRITE_TABLE      = 0b00001000;
+        const BACKGROUND_TABLE  = 0b00010000;
+        const SPRITE_SIZE       = 0b00100000;
+        const MASTER_SLAVE      = 0b01000000;
+        const NMI_ENABLE        = 0b10000000;
+    }
+}
+
+bitflags! {
+    pub struct PpuMask: u8 {
+        const GRAYSCALE         = 0b00000001;
+        const BACKGROUND_LEFT   = 0b00000010;
+        const SPRITES_LEFT      = 0b00000100;
+        const SHOW_BACKGROUND   = 0b00001000;
+        const SHOW_SPRITES      = 0b00010000;
+        const EMPHASIZE_RED     = 0b00100000;
+        const EMPHASIZE_GREEN   = 0b01000000;
+        const EMPHASIZE_BLUE    = 0b10000000;
+    }
+}
+
+bitflags! {
+    pub struct PpuStatus: u8 {
+        const SPRITE_OVERFLOW   = 0b00100000;
+        const SPRITE_ZERO_HIT   = 0b01000000;
+        const VBLANK            = 0b10000000;
+    }
+}
+
+//2C02: регистры $2000-$2007, 2 KiB таблиц имен, палитра и OAM.
+//Кадр - 262 (PAL и Dendy - 312) строки по 341 точке, на выходе индексы палитры 256x240:
+//биты 0-5 - цвет, 6-8 - подчеркивание цвета из PPUMASK
+pub struct PPU {
+    region: Region,
+    ctrl: PpuCtrl,
+    mask: PpuMask,
+    status: PpuStatus,
+
+    oam_address: u8,
+    pub oam: [u8; OAM_SIZE],
+    secondary_oam: [u8; SECONDARY_OAM_SIZE],
+    vram: [u8; VRAM_SIZE],
+    palette: [u8; PALETTE_SIZE],
+
+    //Внутренние регистры прокрутки (loopy): v - текущий адрес, t - временный,
+    //оба в формате yyy NN YYYYY XXXXX, x - точная прокрутка, w - защелка записи
+    vram_address: u16,
+    temp_address: u16,
+    fine_x: u8,
+    write_latch: bool,
+    read_buffer: u8,
+    //Защелка шины данных регистров: ее возвращают биты, которые регистр не выставляет
+    io_latch: u8,
+    io_latch_refresh: [u64; 8],
+
+    next_tile: u8,
+    next_attribute: u8,
+    next_pattern_low: u8,
+    next_pattern_high: u8,
+    pattern_shift_low: u16,
+    pattern_shift_high: u16,
+    attribute_shift_low: u16,
+    attribute_shift_high: u16,
+
+    //Спрайты строки, найденные при оценке и выбранные на точках 257-320
+    sprite_count: usize,
+    sprite_zero_line: bool,
+    sprite_patterns_low: [u8; SPRITES_PER_LINE],
+    sprite_patterns_high: [u8; SPRITES_PER_LINE],
+    sprite_attributes: [u8; SPRITES_PER_LINE],
+    sprite_x: [u8; SPRITES_PER_LINE],
+    //Улучшение: спрайты сверх восьми на строке. Оценка, флаг переполнения и
+    //выборки для игры остаются как у железа, лишние спрайты только рисуются
+    pub unlimited_sprites: bool,
+    extra_sprites: Vec<[u8; 4]>,
+    extra_patterns: Vec<(u8, u8, u8, u8)>,
+
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    //Фаза цветовой поднесущей (0-11) в начале кадра, для NTSC-фильтра
+    pub frame_phase: u16,
+    nmi: bool,
+    //$2002 прочитан за точку до установки vblank
+    suppress_vblank: bool,
+    frame_complete: bool,
+    pub frame_buffer: Vec<u16>,
+}
+
+#[allow(dead_code)]
+impl PPU {
+    pub fn new() -> Self {
+        PPU {
+            region: Region::Ntsc,
+            ctrl: PpuCtrl::empty(),
+            mask: PpuMask::empty(),
+            status: PpuStatus::empty(),
+
+            oam_address: 0,
+            oam: [0; OAM_SIZE],
+            secondary_oam: [0xFF; SECONDARY_OAM_SIZE],
+            vram: [0; VRAM_SIZE],
+            palette: [0; PALETTE_SIZE],
+
+            vram_address: 0,
+            temp_address: 0,
+            fine_x: 0,
+            write_latch: false,
+            read_buffer: 0,
+            io_latch: 0,
+            io_latch_refresh: [0; 8],
+
+            next_tile: 0,
+            next_attribute: 0,
+            next_pattern_low: 0,
+            next_pattern_high: 0,
+            pattern_shift_low: 0,
+            pattern_shift_high: 0,
+            attribute_shift_low: 0,
+            attribute_shift_high: 0,
+
+            sprite_count: 0,
+            sprite_zero_line: false,
+            sprite_patterns_low: [0; SPRITES_PER_LINE],
+            sprite_patterns_high: [0; SPRITES_PER_LINE],
+            sprite_attributes: [0; SPRITES_PER_LINE],
+            sprite_x: [0; SPRITES_PER_LINE],
+            unlimited_sprites: false,
+            extra_sprites: Vec::new(),
+            extra_patterns: Vec::new(),
+
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            frame_phase: 0,
+            nmi: false,
+            suppress_vblank: false,
+            frame_complete: false,
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn read_register(&mut self, address: u16, mapper: &mut dyn Mapper) -> u8 {
+        self.decay_io_latch();
+
+        //Значение и биты, которые регистр выставляет на шину
+        let (value, driven) = match address & 0x2007 {
+            0x2002 => {
+                //Чтение за точку до установки vblank подавляет и флаг, и NMI кадра,
+                //чтение в ту же или следующую точку - только NMI
+                match (self.scanline == self.region.vblank_scanline(), self.dot) {
+                    (true, 1) => self.suppress_vblank = true,
+                    (true, 2..=3) => self.nmi = false,
+                    _ => {}
+                }
+
+                let value = self.status.bits();
+                self.status.remove(PpuStatus::VBLANK);
+                self.write_latch = false;
+                (value, 0xE0)
+            }
+            //Биты 2-4 атрибута спрайта в памяти отсутствуют
+            0x2004 => match self.oam_address & 0b11 {
+                2 => (self.oam[self.oam_address as usize] & 0xE3, 0xFF),
+                _ => (self.oam[self.oam_address as usize], 0xFF),
+            },
+            0x2007 => {
+                let address = self.vram_address & 0x3FFF;
+                let value = match address {
+                    //Палитра читается сразу, а в буфер попадает таблица имен под ней.
+                    //Старшие 2 бита цвета палитра не хранит
+                    0x3F00..=0x3FFF => {
+                        self.read_buffer = self.read(address - 0x1000, mapper);
+                        (self.read(address, mapper), 0x3F)
+                    }
+                    _ => {
+                        let value = self.read_buffer;
+                        self.read_buffer = self.read(address, mapper);
+                        (value, 0xFF)
+                    }
+                };
+
+                self.increment_address();
+                value
+            }
+            //Регистры только для записи
+            _ => (0, 0),
+        };
+
+        self.refresh_io_latch(value, driven)
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8, mapper: &mut dyn Mapper) {
+        let register = address & 0x2007;
+        mapper.ppu_register_write(register, value);
+        self.refresh_io_latch(value, 0xFF);
+
+        match register {
+            0x2000 => {
+                let nmi_enabled = self.ctrl.contains(PpuCtrl::NMI_ENABLE);
+                self.ctrl = PpuCtrl::from_bits_truncate(value);
+                self.temp_address = (self.temp_address & !0x0C00) | (value as u16 & 0b11) << 10;
+
+                //Включение NMI во время vblank сразу вызывает прерывание,
+                //выключение до того, как CPU его заметил, отменяет
+                match (nmi_enabled, self.ctrl.contains(PpuCtrl::NMI_ENABLE)) {
+                    (false, true) => self.nmi |= self.status.contains(PpuStatus::VBLANK),
+                    (true, false) => self.nmi = false,
+                    _ => {}
+                }
+            }
+            0x2001 => self.mask = PpuMask::from_bits_truncate(value),
+            0x2003 => self.oam_address = value,
+            0x2004 => self.write_oam(value),
+            0x2005 => {
+                match self.write_latch {
+                    false => {
+                        self.temp_address = (self.temp_address & !0x001F) | (value >> 3) as u16;
+                        self.fine_x = value & 0b111;
+                    }
+                    true => {
+                        self.temp_address = (self.temp_address & !0x73E0)
+                            | (value as u16 & 0b111) << 12
+                            | (value as u16 >> 3) << 5;
+                    }
+                }
+                self.write_latch = !self.write_latch;
+            }
+            0x2006 => {
+                match self.write_latch {
+                    //Первая запись сбрасывает и бит 14
+                    false => {
+                        self.temp_address =
+                            (self.temp_address & 0x00FF) | (value as u16 & 0x3F) << 8;
+                    }
+                    true => {
+                        self.temp_address = (self.temp_address & 0xFF00) | value as u16;
+                        self.vram_address = self.temp_address;
+                    }
+                }
+                self.write_latch = !self.write_latch;
+            }
+            0x2007 => {
+                self.write(self.vram_address & 0x3FFF, value, mapper);
+                self.increment_address();
+            }
+            _ => {}
+        }
+    }
+
+    //Запись OAM DMA, то же что $2004
+    pub fn write_oam(&mut self, value: u8) {
+        self.oam[self.oam_address as usize] = value;
+        self.oam_address = self.oam_address.wrapping_add(1);
+    }
+
+    //Одна точка PPU
+    pub fn tick(&mut self, mapper: &mut dyn Mapper) {
+        match self.scanline {
+            0..=239 => self.render_dot(mapper),
+            scanline if scanline == self.region.vblank_scanline() && self.dot == 1 => {
+                if !std::mem::replace(&mut self.suppress_vblank, false) {
+                    self.status.insert(PpuStatus::VBLANK);
+                    self.nmi |= self.ctrl.contains(PpuCtrl::NMI_ENABLE);
+                }
+                self.frame_complete = true;
+            }
+            scanline if scanline == self.region.pre_render_scanline() => {
+                if self.dot == 1 {
+                    self.status.remove(
+                        PpuStatus::VBLANK | PpuStatus::SPRITE_ZERO_HIT | PpuStatus::SPRITE_OVERFLOW,
+                    );
+                }
+                self.render_dot(mapper);
+            }
+            _ => {}
+        }
+
+        self.advance();
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    //Запрос NMI для CPU, сбрасывается при чтении
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::replace(&mut self.nmi, false)
+    }
+
+    pub fn poll_frame(&mut self) -> bool {
+        std::mem::replace(&mut self.frame_complete, false)
+    }
+
+    fn refresh_io_latch(&mut self, value: u8, driven: u8) -> u8 {
+        self.io_latch = (self.io_latch & !driven) | (value & driven);
+        for (bit, refresh) in self.io_latch_refresh.iter_mut().enumerate() {
+            if driven & (1 << bit) != 0 {
+                *refresh = self.frame;
+            }
+        }
+
+        self.io_latch
+    }
+
+    fn decay_io_latch(&mut self) {
+        for (bit, &refresh) in self.io_latch_refresh.iter().enumerate() {
+            if self.frame - refresh >= IO_LATCH_DECAY_FRAMES {
+                self.io_latch &= !(1 << bit);
+            }
+        }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask
+            .intersects(PpuMask::SHOW_BACKGROUND | PpuMask::SHOW_SPRITES)
+    }
+
+    fn advance(&mut self) {
+        //В нечетных кадрах с включенной отрисовкой строка подготовки короче на точку
+        let odd_skip = self.region.skips_odd_dot()
+            && self.scanline == self.region.pre_render_scanline()
+            && self.dot == 339
+            && self.frame & 1 == 1
+            && self.rendering_enabled();
+
+        //Строка из 341 точки сдвигает фазу на 4, пропущенная точка - на 8 назад
+        if odd_skip {
+            self.frame_phase = (self.frame_phase + 4) % 12;
+        }
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE || odd_skip {
+            self.dot = 0;
+            self.scanline += 1;
+
+            if self.scanline > self.region.pre_render_scanline() {
+                self.scanline = 0;
+                self.frame += 1;
+                self.frame_phase = (self.frame_phase + 4) % 12;
+            }
+        }
+    }
+
+    //Во время отрисовки доступ к $2007 сдвигает v как выборка фона:
+    //одновременно грубый X и Y вместо обычного шага 1/32
+    fn increment_address(&mut self) {
+        let rendering_line = self.scanline < SCREEN_HEIGHT as u16
+            || self.scanline == self.region.pre_render_scanline();
+
+        if rendering_line && self.rendering_enabled() {
+            self.increment_x();
+            self.increment_y();
+            return;
+        }
+
+        let step = match self.ctrl.contains(PpuCtrl::INCREMENT_32) {
+            true => 32,
+            false => 1,
+        };
+        self.vram_address = self.vram_address.wrapping_add(step) & 0x7FFF;
+    }
+
+    fn read(&mut self, address: u16, mapper: &mut dyn Mapper) -> u8 {
+        match address {
+            0x0000..=0x1FFF => match mapper.pattern_ciram_page(address) {
+                Some(page) => self.vram[ciram_index(page as usize, address)],
+                None => mapper.ppu_read(address),
+            },
+            0x2000..=0x3EFF => {
+                let address = 0x2000 | (address & 0x0FFF);
+                match mapper.read_nametable(address) {
+                    Some(value) => value,
+                    None => self.vram[nametable_index(address, mapper.mirroring())],
+                }
+            }
+            _ => self.palette[palette_index(address)],
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8, mapper: &mut dyn Mapper) {
+        match address {
+            0x0000..=0x1FFF => match mapper.pattern_ciram_page(address) {
+                Some(page) => self.vram[ciram_index(page as usize, address)] = value,
+                None => mapper.ppu_write(address, value),
+            },
+            0x2000..=0x3EFF => {
+                let address = 0x2000 | (address & 0x0FFF);
+                if !mapper.write_nametable(address, value) {
+                    self.vram[nametable_index(address, mapper.mirroring())] = value;
+                }
+            }
+            _ => self.palette[palette_index(address)] = value & 0x3F,
+        }
+    }
+
+    fn render_dot(&mut self, mapper: &mut dyn Mapper) {
+        let dot = self.dot;
+        let visible = self.scanline < SCREEN_HEIGHT as u16;
+
+        if visible && (1..=256).contains(&dot) {
+            self.output_pixel(dot as usize - 1);
+        }
+
+        if !self.rendering_enabled() {
+            return;
+        }
+
+        match dot {
+            1..=256 | 321..=336 => {
+                self.shift_background();
+                self.fetch_background(mapper);
+
+                if dot == 256 {
+                    self.increment_y();
+                    self.evaluate_sprites();
+                }
+            }
+            257..=320 => {
+                if dot == 257 {
+                    self.reload_x();
+                }
+                self.oam_address = 0;
+                if !visible && (280..=304).contains(&dot) {
+                    self.reload_y();
+                }
+                self.fetch_sprite(mapper);
+
+                if dot == 320 {
+                    self.fetch_extra_sprites(mapper);
+                }
+            }
+            //Две лишние выборки таблицы имен в конце строки
+            337 | 339 => {
+                self.read(0x2000 | (self.vram_address & 0x0FFF), mapper);
+            }
+            _ => {}
+        }
+    }
+
+    //Каждый тайл - 8 точек: имя, атрибут, младший и старший байт шаблона
+    fn fetch_background(&mut self, mapper: &mut dyn Mapper) {
+        let address = self.vram_address;
+
+        match (self.dot - 1) % 8 {
+            0 => self.next_tile = self.read(0x2000 | (address & 0x0FFF), mapper),
+            2 => {
+                let attribute_address =
+                    0x23C0 | (address & 0x0C00) | ((address >> 4) & 0x38) | ((address >> 2) & 0x07);
+                let shift = ((address >> 4) & 0b100) | (address & 0b10);
+                self.next_attribute = (self.read(attribute_address, mapper) >> shift) & 0b11;
+            }
+            4 => self.next_pattern_low = self.read(self.background_pattern_address(), mapper),
+            6 => self.next_pattern_high = self.read(self.background_pattern_address() + 8, mapper),
+            7 => {
+                self.load_background();
+                self.increment_x();
+            }
+            _ => {}
+        }
+    }
+
+    //Оценка спрайтов следующей строки во вторичную OAM. После восьми найденных
+    //спрайтов железо ищет переполнение, ошибочно сдвигая и номер байта
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam = [0xFF; SECONDARY_OAM_SIZE];
+        self.sprite_count = 0;
+        self.sprite_zero_line = false;
+        self.extra_sprites.clear();
+
+        //Строка подготовки спрайты для строки 0 не ищет
+        if self.scanline == self.region.pre_render_scanline() {
+            return;
+        }
+
+        let height = self.sprite_height();
+        let scanline = self.scanline;
+        let in_range = |y: u8| scanline.wrapping_sub(y as u16) < height;
+
+        let mut sprite = 0;
+        while sprite < 64 && self.sprite_count < SPRITES_PER_LINE {
+            let entry = sprite * 4;
+            if in_range(self.oam[entry]) {
+                let slot = self.sprite_count * 4;
+                self.secondary_oam[slot..slot + 4].copy_from_slice(&self.oam[entry..entry + 4]);
+                self.sprite_zero_line |= sprite == 0;
+                self.sprite_count += 1;
+            }
+            sprite += 1;
+        }
+
+        if self.unlimited_sprites {
+            for entry in self.oam[sprite * 4..].chunks(4) {
+                if in_range(entry[0]) {
+                    self.extra_sprites
+                        .push([entry[0], entry[1], entry[2], entry[3]]);
+                }
+            }
+        }
+
+        let mut byte = 0;
+        while sprite < 64 {
+            if in_range(self.oam[sprite * 4 + byte]) {
+                self.status.insert(PpuStatus::SPRITE_OVERFLOW);
+                break;
+            }
+            sprite += 1;
+            byte = (byte + 1) & 0b11;
+        }
+    }
+
+    //Выборки спрайтов на точках 257-320: два чтения таблицы имен и два байта шаблона.
+    //Пустые слоты читают тайл $FF, но результат отбрасывается
+    fn fetch_sprite(&mut self, mapper: &mut dyn Mapper) {
+        let slot = (self.dot - 257) as usize / 8;
+        let entry = &self.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attribute, x) = (entry[0], entry[1], entry[2], entry[3]);
+        let used = slot < self.sprite_count;
+
+        let row = match used {
+            true => self.scanline.wrapping_sub(y as u16),
+            false => 0,
+        };
+        let address = self.sprite_pattern_address(tile, attribute, row);
+
+        match (self.dot - 1) % 8 {
+            0 | 2 => {
+                self.read(0x2000 | (self.vram_address & 0x0FFF), mapper);
+            }
+            4 => {
+                let pattern = self.read(address, mapper);
+                self.sprite_patterns_low[slot] = pattern_row(pattern, attribute, used);
+            }
+            6 => {
+                let pattern = self.read(address + 8, mapper);
+                self.sprite_patterns_high[slot] = pattern_row(pattern, attribute, used);
+                self.sprite_attributes[slot] = attribute;
+                self.sprite_x[slot] = x;
+            }
+            _ => {}
+        }
+    }
+
+    //Шаблоны лишних спрайтов читаются мимо шины, чтобы не сбить счетчики мапперов
+    fn fetch_extra_sprites(&mut self, mapper: &mut dyn Mapper) {
+        self.extra_patterns.clear();
+
+        for index in 0..self.extra_sprites.len() {
+            let [y, tile, attribute, x] = self.extra_sprites[index];
+            let row = self.scanline.wrapping_sub(y as u16);
+            let address = self.sprite_pattern_address(tile, attribute, row);
+
+            let low = pattern_row(self.peek_pattern(address, mapper), attribute, true);
+            let high = pattern_row(self.peek_pattern(address + 8, mapper), attribute, true);
+            self.extra_patterns.push((low, high, attribute, x));
+        }
+    }
+
+    //Шаблон может лежать и в CIRAM (Namco 163)
+    fn peek_pattern(&self, address: u16, mapper: &mut dyn Mapper) -> u8 {
+        match mapper.pattern_ciram_page(address) {
+            Some(page) => self.vram[ciram_index(page as usize, address)],
+            None => mapper.peek_chr(address),
+        }
+    }
+
+    fn sprite_height(&self) -> u16 {
+        match self.ctrl.contains(PpuCtrl::SPRITE_SIZE) {
+            true => 16,
+            false => 8,
+        }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let table = match self.ctrl.contains(PpuCtrl::BACKGROUND_TABLE) {
+            true => 0x1000,
+            false => 0x0000,
+        };
+        table + (self.next_tile as u16) * 16 + (self.vram_address >> 12)
+    }
+
+    fn sprite_pattern_address(&self, tile: u8, attribute: u8, row: u16) -> u16 {
+        let height = self.sprite_height();
+        let row = match attribute & 0x80 != 0 {
+            true => (height - 1).wrapping_sub(row) & (height - 1),
+            false => row & (height - 1),
+        };
+
+        //В режиме 8x16 бит 0 номера тайла выбирает таблицу, нижняя половина - следующий тайл
+        let (table, tile) = match (height, self.ctrl.contains(PpuCtrl::SPRITE_TABLE)) {
+            (16, _) => ((tile as u16 & 1) << 12, (tile as u16 & 0xFE) + (row >> 3)),
+            (_, true) => (0x1000, tile as u16),
+            (_, false) => (0x0000, tile as u16),
+        };
+
+        table | tile << 4 | (row & 0b111)
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_shift_low <<= 1;
+        self.pattern_shift_high <<= 1;
+        self.attribute_shift_low <<= 1;
+        self.attribute_shift_high <<= 1;
+    }
+
+    fn load_background(&mut self) {
+        self.pattern_shift_low |= self.next_pattern_low as u16;
+        self.pattern_shift_high |= self.next_pattern_high as u16;
+        self.attribute_shift_low |= ((self.next_attribute & 0b01) as u16) * 0xFF;
+        self.attribute_shift_high |= ((self.next_attribute >> 1) as u16) * 0xFF;
+    }
+
+    fn increment_x(&mut self) {
+        match self.vram_address & 0x001F {
+            31 => self.vram_address = (self.vram_address & !0x001F) ^ 0x0400,
+            _ => self.vram_address += 1,
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.vram_address & 0x7000 != 0x7000 {
+            self.vram_address += 0x1000;
+            return;
+        }
+
+        self.vram_address &= !0x7000;
+        let coarse_y = match (self.vram_address & 0x03E0) >> 5 {
+            29 => {
+                self.vram_address ^= 0x0800;
+                0
+            }
+            31 => 0,
+            y => y + 1,
+        };
+        self.vram_address = (self.vram_address & !0x03E0) | (coarse_y << 5);
+    }
+
+    //Точка 257: горизонтальная часть t копируется в v
+    fn reload_x(&mut self) {
+        self.vram_address = (self.vram_address & !0x041F) | (self.temp_address & 0x041F);
+    }
+
+    //Точки 280-304 строки подготовки: вертикальная часть t копируется в v
+    fn reload_y(&mut self) {
+        self.vram_address = (self.vram_address & !0x7BE0) | (self.temp_address & 0x7BE0);
+    }
+
+    fn output_pixel(&mut self, x: usize) {
+        let (background_pixel, background_palette) = self.background_pixel(x);
+        let sprite = self.sprite_pixel(x);
+
+        let palette_address = match (background_pixel, sprite) {
+            (0, None) => 0,
+            (0, Some((pixel, palette, _, _))) => 0x10 | palette << 2 | pixel,
+            (_, None) => background_palette << 2 | background_pixel,
+            (_, Some((pixel, palette, behind, zero))) => {
+                if zero && x != 255 {
+                    self.status.insert(PpuStatus::SPRITE_ZERO_HIT);
+                }
+
+                match behind {
+                    true => background_palette << 2 | background_pixel,
+                    false => 0x10 | palette << 2 | pixel,
+                }
+            }
+        };
+
+        let mut color = self.palette[palette_index(palette_address as u16)];
+        if self.mask.contains(PpuMask::GRAYSCALE) {
+            color &= 0x30;
+        }
+        //У 2C07 и Dendy биты подчеркивания красного и зеленого переставлены
+        let emphasis = (self.mask.bits() >> 5) as u16;
+        let emphasis = match self.region {
+            Region::Ntsc => emphasis,
+            Region::Pal | Region::Dendy => {
+                emphasis & 0b100 | (emphasis & 1) << 1 | (emphasis >> 1) & 1
+            }
+        };
+
+        self.frame_buffer[self.scanline as usize * SCREEN_WIDTH + x] = color as u16 | emphasis << 6;
+    }
+
+    //(пиксель, палитра)
+    fn background_pixel(&self, x: usize) -> (u8, u8) {
+        let show_background = self.mask.contains(PpuMask::SHOW_BACKGROUND)
+            && (x >= 8 || self.mask.contains(PpuMask::BACKGROUND_LEFT));
+
+        if !show_background {
+            return (0, 0);
+        }
+
+        let bit = 15 - self.fine_x;
+        let pixel =
+            ((self.pattern_shift_low >> bit) & 1) | ((self.pattern_shift_high >> bit) & 1) << 1;
+        let palette =
+            ((self.attribute_shift_low >> bit) & 1) | ((self.attribute_shift_high >> bit) & 1) << 1;
+
+        (pixel as u8, palette as u8)
+    }
+
+    //(пиксель, палитра, за фоном, спрайт 0). Слот с меньшим номером перекрывает остальные
+    fn sprite_pixel(&self, x: usize) -> Option<(u8, u8, bool, bool)> {
+        let show_sprites = self.mask.contains(PpuMask::SHOW_SPRITES)
+            && (x >= 8 || self.mask.contains(PpuMask::SPRITES_LEFT));
+
+        if !show_sprites {
+            return None;
+        }
+
+        let sprites = (0..self.sprite_count)
+            .map(|slot| {
+                (
+                    self.sprite_patterns_low[slot],
+                    self.sprite_patterns_high[slot],
+                    self.sprite_attributes[slot],
+                    self.sprite_x[slot],
+                )
+            })
+            .chain(self.extra_patterns.iter().copied());
+
+        sprites
+            .enumerate()
+            .find_map(|(slot, (low, high, attribute, sprite_x))| {
+                let column = x.wrapping_sub(sprite_x as usize);
+                if column >= 8 {
+                    return None;
+                }
+
+                let bit = 7 - column;
+                let pixel = ((low >> bit) & 1) | ((high >> bit) & 1) << 1;
+
+                match pixel {
+                    0 => None,
+                    _ => {
+                        let zero = slot == 0 && self.sprite_zero_line;
+                        Some((pixel, attribute & 0b11, attribute & 0x20 != 0, zero))
+                    }
+                }
+            })
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        PPU::new()
+    }
+}
+
+//Горизонтальное отражение разворачивает байт шаблона
+fn pattern_row(pattern: u8, attribute: u8, used: bool) -> u8 {
+    match (used, attribute & 0x40 != 0) {
+        (false, _) => 0,
+        (true, true) => pattern.reverse_bits(),
+        (true, false) => pattern,
+    }
+}
+
+fn nametable_index(address: u16, mirroring: Mirroring) -> usize {
+    let table = (address as usize >> 10) & 0b11;
+    ciram_index(mirroring.page(table), address)
+}
+
+fn ciram_index(page: usize, address: u16) -> usize {
+    page * 0x400 + (address as usize & 0x03FF)
+}
+
+//$3F10/$3F14/$3F18/$3F1C - зеркала фоновых цветов $3F00/$3F04/$3F08/$3F0C
+fn palette_index(address: u16) -> usize {
+    let index = address as usize & 0x1F;
+    match index & 0x13 {
+        0x10 => index & 0x0F,
+        _ => index,
+    }
+}
+
+#[cfg(test)]
+mod ppu_test {
+    use super::*;
+
+    const PRE_RENDER_SCANLINE: u16 = 261;
+
+    struct Board {
+        chr: Vec<u8>,
+        mirroring: Mirroring,
+        ciram_page: Option<u8>,
+    }
+
+    impl Mapper for Board {
+        fn cpu_read(&mut self, _address: u16) -> Option<u8> {
+            None
+        }
+
+        fn cpu_write(&mut self, _address: u16, _value: u8) {}
+
+        fn ppu_read(&mut self, address: u16) -> u8 {
+            self.chr[address as usize]
+        }
+
+        fn ppu_write(&mut self, address: u16, value: u8) {
+            self.chr[address as usize] = value;
+        }
+
+        fn mirroring(&self) -> Mirroring {
+            self.mirroring
+        }
+
+        fn pattern_ciram_page(&self, _address: u16) -> Option<u8> {
+            self.ciram_page
+        }
+    }
+
+    fn board(mirroring: Mirroring) -> Board {
+        Board {
+            chr: vec![0; 0x2000],
+            mirroring,
+            ciram_page: None,
+        }
+    }
+
+    fn set_address(ppu: &mut PPU, board: &mut Board, address: u16) {
+        ppu.write_register(0x2006, (address >> 8) as u8, board);
+        ppu.write_register(0x2006, address as u8, board);
+    }
+
+    fn run_to(ppu: &mut PPU, board: &mut Board, scanline: u16, dot: u16) {
+        while ppu.scanline != scanline || ppu.dot != dot {
+            ppu.tick(board);
+        }
+    }
+
+    #[test]
+    fn test_read_buffer() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        set_address(&mut ppu, &mut board, 0x2000);
+        ppu.write_register(0x2007, 0x11, &mut board);
+        ppu.write_register(0x2007, 0x22, &mut board);
+
+        set_address(&mut ppu, &mut board, 0x2000);
+        ppu.read_register(0x2007, &mut board);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0x11);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0x22);
+    }
+
+    #[test]
+    fn test_palette_mirrors() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        set_address(&mut ppu, &mut board, 0x3F10);
+        ppu.write_register(0x2007, 0x2A, &mut board);
+
+        set_address(&mut ppu, &mut board, 0x3F00);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0x2A);
+
+        set_address(&mut ppu, &mut board, 0x3F24);
+        ppu.write_register(0x2007, 0x15, &mut board);
+        assert_eq!(ppu.palette[4], 0x15);
+    }
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Horizontal);
+
+        set_address(&mut ppu, &mut board, 0x2005);
+        ppu.write_register(0x2007, 0x77, &mut board);
+
+        set_address(&mut ppu, &mut board, 0x2405);
+        ppu.read_register(0x2007, &mut board);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0x77);
+
+        set_address(&mut ppu, &mut board, 0x2805);
+        ppu.read_register(0x2007, &mut board);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0x00);
+    }
+
+    #[test]
+    fn test_four_screen_and_custom_mirroring() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::FourScreen);
+
+        let read = |ppu: &mut PPU, board: &mut Board, address: u16| {
+            set_address(ppu, board, address);
+            ppu.read_register(0x2007, board);
+            ppu.read_register(0x2007, board)
+        };
+
+        for table in 0..4 {
+            set_address(&mut ppu, &mut board, 0x2000 + table * 0x400);
+            ppu.write_register(0x2007, table as u8 + 1, &mut board);
+        }
+        for table in 0..4 {
+            assert_eq!(
+                read(&mut ppu, &mut board, 0x2000 + table * 0x400),
+                table as u8 + 1
+            );
+        }
+
+        //Маппер переключает страницы между обращениями
+        board.mirroring = Mirroring::Custom([1, 0, 0, 1]);
+        assert_eq!(read(&mut ppu, &mut board, 0x2000), 2);
+        assert_eq!(read(&mut ppu, &mut board, 0x2400), 1);
+        assert_eq!(read(&mut ppu, &mut board, 0x2C00), 2);
+    }
+
+    #[test]
+    fn test_ciram_patterns() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        board.ciram_page = Some(1);
+        set_address(&mut ppu, &mut board, 0x0010);
+        ppu.write_register(0x2007, 0x5A, &mut board);
+        assert_eq!(board.chr[0x0010], 0);
+        assert_eq!(ppu.peek_pattern(0x0010, &mut board), 0x5A);
+
+        //Та же ячейка CIRAM видна как таблица имен 1
+        board.ciram_page = None;
+        set_address(&mut ppu, &mut board, 0x2410);
+        ppu.read_register(0x2007, &mut board);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0x5A);
+    }
+
+    #[test]
+    fn test_io_latch() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        ppu.write_register(0x2003, 0x1F, &mut board);
+        assert_eq!(ppu.read_register(0x2000, &mut board), 0x1F);
+        assert_eq!(ppu.read_register(0x2002, &mut board) & 0x1F, 0x1F);
+
+        //Палитра выставляет только 6 бит
+        set_address(&mut ppu, &mut board, 0x3F00);
+        ppu.write_register(0x2001, 0xC0, &mut board);
+        assert_eq!(ppu.read_register(0x2007, &mut board), 0xC0);
+
+        //Без обновления защелка разряжается
+        ppu.frame += IO_LATCH_DECAY_FRAMES;
+        assert_eq!(ppu.read_register(0x2005, &mut board), 0);
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+        ppu.write_register(0x2000, 0x80, &mut board);
+
+        run_to(&mut ppu, &mut board, 241, 1);
+        assert!(!ppu.poll_nmi());
+
+        ppu.tick(&mut board);
+        assert!(ppu.poll_nmi());
+        assert!(ppu.poll_frame());
+        assert_eq!(ppu.read_register(0x2002, &mut board) & 0x80, 0x80);
+        assert_eq!(ppu.read_register(0x2002, &mut board) & 0x80, 0);
+    }
+
+    #[test]
+    fn test_background_render() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        //Тайл 1: первая строка - цвет 3, остальные - цвет 1
+        board.chr[0x10] = 0xFF;
+        board.chr[0x18] = 0x01;
+        for line in 1..8 {
+            board.chr[0x10 + line] = 0xFF;
+        }
+
+        set_address(&mut ppu, &mut board, 0x2001);
+        ppu.write_register(0x2007, 1, &mut board);
+        set_address(&mut ppu, &mut board, 0x3F00);
+        for color in [0x0F, 0x01, 0x02, 0x03].iter() {
+            ppu.write_register(0x2007, *color, &mut board);
+        }
+
+        ppu.write_register(0x2000, 0, &mut board);
+        ppu.write_register(0x2005, 0, &mut board);
+        ppu.write_register(0x2005, 0, &mut board);
+        ppu.write_register(0x2001, 0b0000_1010, &mut board);
+
+        //Первый кадр без строки подготовки, смотрим второй
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, &mut board, 241, 0);
+
+        assert_eq!(&ppu.frame_buffer[0..8], &[0x0F; 8]);
+        assert_eq!(
+            &ppu.frame_buffer[8..16],
+            &[0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x03]
+        );
+        assert_eq!(ppu.frame_buffer[SCREEN_WIDTH + 8], 0x01);
+    }
+
+    #[test]
+    fn test_loopy_registers() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        ppu.write_register(0x2000, 0b10, &mut board);
+        assert_eq!(ppu.temp_address, 0x0800);
+
+        ppu.read_register(0x2002, &mut board);
+        ppu.write_register(0x2005, 0b0111_1101, &mut board);
+        assert_eq!(ppu.temp_address, 0x080F);
+        assert_eq!(ppu.fine_x, 0b101);
+
+        ppu.write_register(0x2005, 0b0101_1110, &mut board);
+        assert_eq!(ppu.temp_address, 0x696F);
+
+        ppu.write_register(0x2006, 0b0011_1101, &mut board);
+        assert_eq!(ppu.temp_address, 0x3D6F);
+        assert_eq!(ppu.vram_address, 0);
+
+        ppu.write_register(0x2006, 0b1111_0000, &mut board);
+        assert_eq!(ppu.temp_address, 0x3DF0);
+        assert_eq!(ppu.vram_address, 0x3DF0);
+    }
+
+    #[test]
+    fn test_scroll_copies() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+        ppu.write_register(0x2001, 0b0000_1000, &mut board);
+
+        ppu.write_register(0x2000, 0b01, &mut board);
+        ppu.write_register(0x2005, 0x10, &mut board);
+        ppu.write_register(0x2005, 0x21, &mut board);
+
+        //Вертикальная часть копируется на строке подготовки
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 305);
+        assert_eq!(ppu.vram_address & 0x7BE0, 0x1080);
+
+        //Горизонтальная - на точке 257 каждой строки, затем два тайла предвыборки
+        run_to(&mut ppu, &mut board, 0, 0);
+        assert_eq!(ppu.vram_address & 0x041F, 0x0404);
+
+        run_to(&mut ppu, &mut board, 0, 257);
+        assert_eq!(ppu.vram_address & 0x7000, 0x2000);
+
+        //Запись $2006 посреди кадра сразу меняет v
+        ppu.write_register(0x2006, 0x23, &mut board);
+        ppu.write_register(0x2006, 0x45, &mut board);
+        assert_eq!(ppu.vram_address, 0x2345);
+    }
+
+    #[test]
+    fn test_2007_during_rendering() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+        ppu.write_register(0x2001, 0b0000_1000, &mut board);
+
+        run_to(&mut ppu, &mut board, 10, 260);
+        let before = ppu.vram_address;
+        ppu.read_register(0x2007, &mut board);
+
+        assert_eq!(ppu.vram_address & 0x001F, (before & 0x001F) + 1);
+        assert_eq!(ppu.vram_address & 0x7000, (before & 0x7000) + 0x1000);
+    }
+
+    //Фон целиком из сплошного тайла 1, спрайт 0 - тоже тайл 1
+    fn sprite_zero_setup(x: u8, mask: u8) -> (PPU, Board) {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+        for line in 0..8 {
+            board.chr[0x10 + line] = 0xFF;
+        }
+
+        set_address(&mut ppu, &mut board, 0x2000);
+        for _ in 0..0x3C0 {
+            ppu.write_register(0x2007, 1, &mut board);
+        }
+        ppu.oam[0..4].copy_from_slice(&[30, 1, 0, x]);
+
+        ppu.write_register(0x2000, 0, &mut board);
+        ppu.write_register(0x2005, 0, &mut board);
+        ppu.write_register(0x2005, 0, &mut board);
+        ppu.write_register(0x2001, mask, &mut board);
+
+        (ppu, board)
+    }
+
+    fn sprite_zero_hit(ppu: &PPU) -> bool {
+        ppu.status.contains(PpuStatus::SPRITE_ZERO_HIT)
+    }
+
+    #[test]
+    fn test_sprite_evaluation_limit() {
+        let mut ppu = PPU::new();
+        for sprite in 0..9 {
+            ppu.oam[sprite * 4] = 10;
+        }
+        for sprite in 9..64 {
+            ppu.oam[sprite * 4] = 0xF0;
+        }
+
+        ppu.scanline = 12;
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.sprite_count, 8);
+        assert!(ppu.sprite_zero_line);
+        assert!(ppu.status.contains(PpuStatus::SPRITE_OVERFLOW));
+
+        ppu.status.remove(PpuStatus::SPRITE_OVERFLOW);
+        ppu.scanline = 18;
+        ppu.evaluate_sprites();
+        assert_eq!(ppu.sprite_count, 0);
+        assert!(!ppu.status.contains(PpuStatus::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_sprite_overflow_bug() {
+        let mut ppu = PPU::new();
+        ppu.oam = [0xF0; OAM_SIZE];
+        for sprite in 0..8 {
+            ppu.oam[sprite * 4] = 10;
+        }
+        ppu.scanline = 10;
+
+        //Девятый спрайт на строке есть, но вместо Y проверяется номер тайла
+        ppu.oam[9 * 4] = 10;
+        ppu.evaluate_sprites();
+        assert!(!ppu.status.contains(PpuStatus::SPRITE_OVERFLOW));
+
+        //Спрайтов на строке восемь, но номер тайла попадает в диапазон
+        ppu.oam[9 * 4] = 0xF0;
+        ppu.oam[9 * 4 + 1] = 10;
+        ppu.evaluate_sprites();
+        assert!(ppu.status.contains(PpuStatus::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_unlimited_sprites() {
+        let render = |unlimited: bool| {
+            let mut ppu = PPU::new();
+            let mut board = board(Mirroring::Vertical);
+            for line in 0..8 {
+                board.chr[0x10 + line] = 0xFF;
+            }
+            ppu.oam = [0xF0; OAM_SIZE];
+            for sprite in 0..10 {
+                ppu.oam[sprite * 4..sprite * 4 + 4].copy_from_slice(&[
+                    30,
+                    1,
+                    0,
+                    sprite as u8 * 10 + 8,
+                ]);
+            }
+            ppu.unlimited_sprites = unlimited;
+
+            set_address(&mut ppu, &mut board, 0x3F11);
+            ppu.write_register(0x2007, 0x16, &mut board);
+            ppu.write_register(0x2001, 0b0001_0100, &mut board);
+
+            run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+            run_to(&mut ppu, &mut board, 33, 0);
+            ppu
+        };
+
+        let line = 32 * SCREEN_WIDTH;
+        let ppu = render(false);
+        assert_eq!(ppu.frame_buffer[line + 78], 0x16);
+        assert_eq!(ppu.frame_buffer[line + 88], 0x00);
+        assert!(ppu.status.contains(PpuStatus::SPRITE_OVERFLOW));
+
+        let ppu = render(true);
+        assert_eq!(ppu.frame_buffer[line + 88], 0x16);
+        assert_eq!(ppu.frame_buffer[line + 98], 0x16);
+        assert_eq!(ppu.sprite_count, 8);
+        assert!(ppu.status.contains(PpuStatus::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        let (mut ppu, mut board) = sprite_zero_setup(20, 0b0001_1110);
+
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, &mut board, 31, 21);
+        assert!(!sprite_zero_hit(&ppu));
+
+        ppu.tick(&mut board);
+        assert!(sprite_zero_hit(&ppu));
+
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 2);
+        assert!(!sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_edge_cases() {
+        //Точка 255 не дает попадания
+        let (mut ppu, mut board) = sprite_zero_setup(255, 0b0001_1110);
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, &mut board, 240, 0);
+        assert!(!sprite_zero_hit(&ppu));
+
+        //Левая колонка обрезана у фона или у спрайтов
+        for &mask in [0b0001_1100, 0b0001_1010].iter() {
+            let (mut ppu, mut board) = sprite_zero_setup(0, mask);
+            run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+            run_to(&mut ppu, &mut board, 240, 0);
+            assert!(!sprite_zero_hit(&ppu));
+        }
+
+        let (mut ppu, mut board) = sprite_zero_setup(0, 0b0001_1110);
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, &mut board, 240, 0);
+        assert!(sprite_zero_hit(&ppu));
+    }
+
+    #[test]
+    fn test_sprite_flip_and_priority() {
+        let (mut ppu, mut board) = sprite_zero_setup(20, 0b0001_0110);
+        board.chr[0x20] = 0b1000_0000;
+        ppu.oam[4..8].copy_from_slice(&[50, 2, 0x41, 40]);
+        ppu.oam[8..12].copy_from_slice(&[50, 1, 0x02, 40]);
+        set_address(&mut ppu, &mut board, 0x3F15);
+        ppu.write_register(0x2007, 0x21, &mut board);
+        set_address(&mut ppu, &mut board, 0x3F19);
+        ppu.write_register(0x2007, 0x22, &mut board);
+        ppu.write_register(0x2000, 0, &mut board);
+
+        run_to(&mut ppu, &mut board, PRE_RENDER_SCANLINE, 0);
+        run_to(&mut ppu, &mut board, 240, 0);
+
+        //Слот 1 перекрывает слот 2 только там, где у него непрозрачный пиксель
+        let line = &ppu.frame_buffer[51 * SCREEN_WIDTH..52 * SCREEN_WIDTH];
+        assert_eq!(line[47], 0x21);
+        assert_eq!(line[40], 0x22);
+    }
+
+    #[test]
+    fn test_vblank_read_race() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+        ppu.write_register(0x2000, 0x80, &mut board);
+
+        //За точку до установки: флаг не выставляется, NMI нет
+        run_to(&mut ppu, &mut board, 241, 1);
+        assert_eq!(ppu.read_register(0x2002, &mut board) & 0x80, 0);
+        ppu.tick(&mut board);
+        assert_eq!(ppu.read_register(0x2002, &mut board) & 0x80, 0);
+        assert!(!ppu.poll_nmi());
+
+        //В точке установки: флаг читается, NMI подавлен
+        run_to(&mut ppu, &mut board, 0, 0);
+        run_to(&mut ppu, &mut board, 241, 2);
+        assert_eq!(ppu.read_register(0x2002, &mut board) & 0x80, 0x80);
+        assert!(!ppu.poll_nmi());
+
+        //Позже чтение NMI не отменяет
+        run_to(&mut ppu, &mut board, 0, 0);
+        run_to(&mut ppu, &mut board, 241, 4);
+        ppu.read_register(0x2002, &mut board);
+        assert!(ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_nmi_enable_toggle() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        run_to(&mut ppu, &mut board, 241, 10);
+        assert!(!ppu.poll_nmi());
+
+        ppu.write_register(0x2000, 0x80, &mut board);
+        assert!(ppu.poll_nmi());
+
+        ppu.write_register(0x2000, 0x80, &mut board);
+        assert!(!ppu.poll_nmi());
+
+        ppu.write_register(0x2000, 0x00, &mut board);
+        ppu.write_register(0x2000, 0x80, &mut board);
+        ppu.write_register(0x2000, 0x00, &mut board);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_odd_frame_skip() {
+        let mut ppu = PPU::new();
+        let mut board = board(Mirroring::Vertical);
+
+        let mut frame_length = |ppu: &mut PPU| {
+            let frame = ppu.frame;
+            let mut dots = 0;
+            while ppu.frame == frame {
+                ppu.tick(&mut board);
+                dots += 1;
+            }
+            dots
+        };
+
+        assert_eq!(frame_length(&mut ppu), 89342);
+        assert_eq!(frame_length(&mut ppu), 89342);
+
+        ppu.mask = PpuMask::SHOW_BACKGROUND;
+        assert_eq!(frame_length(&mut ppu), 89342);
+        assert_eq!(frame_length(&mut ppu), 89341);
+    }
+}
@@ -0,0 +1,182 @@
+use std::f32::consts::PI;
+
+use crate::nes::ppu::palette::{composite_signal, yiq_to_rgb, PaletteSettings};
+use crate::nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//Генератор сигнала 2C02 работает на удвоенной мастер-частоте:
+//8 отсчетов на точку, 12 отсчетов на период поднесущей
+const SAMPLES_PER_DOT: usize = 8;
+const SAMPLES_PER_PERIOD: usize = 12;
+const LINE_SAMPLES: usize = SCREEN_WIDTH * SAMPLES_PER_DOT;
+//Выходной пиксель - 4 отсчета, строка вдвое шире кадра PPU
+const SAMPLES_PER_PIXEL: usize = 4;
+pub const NTSC_WIDTH: usize = LINE_SAMPLES / SAMPLES_PER_PIXEL;
+
+//sharpness -1.0..1.0 - подъем или завал четкости яркости,
+//artifacts 0.0..1.0 - переход перепадов яркости в цвет (радужные края, смешение полосок),
+//fringing 0.0..1.0 - просачивание цветности в яркость (ползущие точки)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscSettings {
+    pub color: PaletteSettings,
+    pub sharpness: f32,
+    pub artifacts: f32,
+    pub fringing: f32,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings {
+            color: PaletteSettings::default(),
+            sharpness: 0.0,
+            artifacts: 1.0,
+            fringing: 1.0,
+        }
+    }
+}
+
+//Кодирует каждую строку кадра в композитный сигнал с фазой поднесущей
+//по точкам и кадрам, затем декодирует обратно в RGB
+pub struct NtscFilter {
+    pub settings: NtscSettings,
+    //Префиксные суммы сигнала строки и произведений цветности на поднесущую
+    signal: Vec<f32>,
+    luma: Vec<f32>,
+    in_phase: Vec<f32>,
+    quadrature: Vec<f32>,
+}
+
+#[allow(dead_code)]
+impl NtscFilter {
+    pub fn new(settings: NtscSettings) -> Self {
+        NtscFilter {
+            settings,
+            signal: vec![0.0; LINE_SAMPLES + 1],
+            luma: vec![0.0; LINE_SAMPLES],
+            in_phase: vec![0.0; LINE_SAMPLES + 1],
+            quadrature: vec![0.0; LINE_SAMPLES + 1],
+        }
+    }
+
+    //Кадр PPU в RGB24 шириной NTSC_WIDTH. frame_phase - фаза поднесущей в начале кадра
+    pub fn filter(&mut self, frame: &[u16], frame_phase: u16) -> Vec<u8> {
+        let mut output = Vec::with_capacity(NTSC_WIDTH * SCREEN_HEIGHT * 3);
+
+        for (line, pixels) in frame.chunks(SCREEN_WIDTH).take(SCREEN_HEIGHT).enumerate() {
+            //Строка сдвигает фазу на 4, первый пиксель выходит в точке 1
+            let phase = (frame_phase as usize + line * 4 + SAMPLES_PER_DOT) % SAMPLES_PER_PERIOD;
+            self.filter_line(pixels, phase, &mut output);
+        }
+
+        output
+    }
+
+    fn filter_line(&mut self, pixels: &[u16], phase: usize, output: &mut Vec<u8>) {
+        let settings = self.settings;
+        let sample_phase = |sample: usize| ((phase + sample) % SAMPLES_PER_PERIOD) as u16;
+
+        for sample in 0..LINE_SAMPLES {
+            let pixel = pixels[sample / SAMPLES_PER_DOT];
+            self.signal[sample + 1] =
+                self.signal[sample] + composite_signal(pixel, sample_phase(sample));
+        }
+
+        for sample in 0..LINE_SAMPLES {
+            self.luma[sample] = average(&self.signal, sample, SAMPLES_PER_PERIOD);
+        }
+
+        //Без артефактов цветность демодулируется из сигнала за вычетом яркости
+        for sample in 0..LINE_SAMPLES {
+            let raw = self.signal[sample + 1] - self.signal[sample];
+            let chroma = raw - (1.0 - settings.artifacts) * self.luma[sample];
+            let angle = PI * sample_phase(sample) as f32 / 6.0 + settings.color.hue.to_radians();
+
+            self.in_phase[sample + 1] = self.in_phase[sample] + chroma * angle.cos();
+            self.quadrature[sample + 1] = self.quadrature[sample] + chroma * angle.sin();
+        }
+
+        for x in 0..NTSC_WIDTH {
+            let center = x * SAMPLES_PER_PIXEL + SAMPLES_PER_PIXEL / 2;
+
+            //Режекция поднесущей - окно в период, fringing сужает его вдвое
+            let notched = self.luma[center];
+            let narrow = average(&self.signal, center, SAMPLES_PER_PERIOD / 2);
+            let wide = average(&self.signal, center, SAMPLES_PER_PERIOD * 2);
+            let y = notched
+                + settings.fringing * (narrow - notched)
+                + settings.sharpness * (notched - wide);
+
+            let i = average(&self.in_phase, center, SAMPLES_PER_PERIOD);
+            let q = average(&self.quadrature, center, SAMPLES_PER_PERIOD);
+
+            output.extend_from_slice(&yiq_to_rgb(y, i, q, &settings.color));
+        }
+    }
+}
+
+impl Default for NtscFilter {
+    fn default() -> Self {
+        NtscFilter::new(NtscSettings::default())
+    }
+}
+
+//Среднее по окну вокруг отсчета через префиксные суммы, у краев строки окно обрезается
+fn average(sums: &[f32], center: usize, width: usize) -> f32 {
+    let samples = sums.len() - 1;
+    let start = center.saturating_sub(width / 2);
+    let end = (start + width).min(samples);
+
+    (sums[end] - sums[start]) / (end - start) as f32
+}
+
+#[cfg(test)]
+mod ntsc_test {
+    use super::*;
+    use crate::nes::ppu::palette::Palette;
+
+    fn frame(pixel: impl Fn(usize) -> u16) -> Vec<u16> {
+        (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|index| pixel(index % SCREEN_WIDTH))
+            .collect()
+    }
+
+    #[test]
+    fn test_flat_color_matches_palette() {
+        let mut filter = NtscFilter::new(NtscSettings {
+            fringing: 0.0,
+            ..NtscSettings::default()
+        });
+        let output = filter.filter(&frame(|_| 0x16), 0);
+        assert_eq!(output.len(), NTSC_WIDTH * SCREEN_HEIGHT * 3);
+
+        let expected = Palette::default().rgb(0x16);
+        let offset = (100 * NTSC_WIDTH + 200) * 3;
+        for (channel, &value) in output[offset..offset + 3].iter().enumerate() {
+            assert!((value as i16 - expected[channel] as i16).abs() <= 2);
+        }
+    }
+
+    #[test]
+    fn test_artifacts() {
+        //Черно-белые столбики через пиксель дают цвет на композитном выходе
+        let stripes = frame(|x| match x & 1 {
+            0 => 0x30,
+            _ => 0x0F,
+        });
+        let chroma = |output: &[u8]| {
+            let offset = (100 * NTSC_WIDTH + 200) * 3;
+            let rgb = &output[offset..offset + 3];
+            rgb.iter().max().unwrap() - rgb.iter().min().unwrap()
+        };
+
+        let mut filter = NtscFilter::default();
+        let composite = filter.filter(&stripes, 0);
+        assert!(chroma(&composite) > 16);
+
+        //Фаза кадра сдвигает артефакты
+        assert_ne!(composite, filter.filter(&stripes, 4));
+
+        filter.settings.artifacts = 0.0;
+        filter.settings.fringing = 0.0;
+        assert!(chroma(&filter.filter(&stripes, 0)) < chroma(&composite));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+//Обрезка краев кадра, которые телевизор прячет за рамкой. Поля в пикселях PPU
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+#[allow(dead_code)]
+impl Overscan {
+    pub fn new(top: usize, bottom: usize, left: usize, right: usize) -> Self {
+        Overscan {
+            top,
+            bottom,
+            left,
+            right,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH.saturating_sub(self.left + self.right)
+    }
+
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT.saturating_sub(self.top + self.bottom)
+    }
+
+    //Подходит для кадра PPU, RGB после палитры и строк NTSC-фильтра:
+    //stride - элементов в строке, кратно ширине экрана
+    pub fn crop<T: Copy>(&self, frame: &[T], stride: usize) -> Vec<T> {
+        let scale = stride / SCREEN_WIDTH;
+        let start = self.left.min(SCREEN_WIDTH) * scale;
+        let end = start + self.width() * scale;
+
+        frame
+            .chunks(stride)
+            .skip(self.top)
+            .take(self.height())
+            .flat_map(|line| line[start..end].iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod overscan_test {
+    use super::*;
+
+    #[test]
+    fn test_crop() {
+        let frame: Vec<u16> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|index| (index / SCREEN_WIDTH) as u16)
+            .collect();
+        let overscan = Overscan::new(8, 8, 0, 0);
+        let cropped = overscan.crop(&frame, SCREEN_WIDTH);
+
+        assert_eq!(cropped.len(), SCREEN_WIDTH * 224);
+        assert_eq!(cropped[0], 8);
+        assert_eq!(cropped[cropped.len() - 1], 231);
+
+        //RGB: три байта на пиксель
+        let rgb: Vec<u8> = (0..SCREEN_WIDTH * 3)
+            .map(|index| (index / 3) as u8)
+            .collect();
+        let overscan = Overscan::new(0, 0, 8, 4);
+        let cropped = overscan.crop(&rgb, SCREEN_WIDTH * 3);
+
+        assert_eq!(overscan.width(), 244);
+        assert_eq!(cropped.len(), 244 * 3);
+        assert_eq!(&cropped[0..3], &[8, 8, 8]);
+        assert_eq!(cropped[cropped.len() - 1], 251);
+    }
+}
@@ -0,0 +1,244 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+//64 цвета x 8 сочетаний битов подчеркивания
+pub const PALETTE_SIZE: usize = 512;
+const BASE_COLORS: usize = 64;
+
+//Уровни композитного сигнала 2C02 (В) для низкой и высокой фазы по яркостям 0-3
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+//Подчеркивание ослабляет сигнал в своей трети периода цветовой поднесущей
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+const GAMMA: f32 = 2.2 / 1.8;
+
+//Ручки генератора: сдвиг оттенка в градусах, множители насыщенности и контраста,
+//добавка яркости
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaletteSettings {
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+}
+
+impl Default for PaletteSettings {
+    fn default() -> Self {
+        PaletteSettings {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+        }
+    }
+}
+
+//Таблица RGB для 9-битных пикселей PPU: биты 0-5 - цвет, 6-8 - подчеркивание из PPUMASK
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+#[allow(dead_code)]
+impl Palette {
+    //Палитра по модели композитного сигнала 2C02: 12 отсчетов на пиксель,
+    //затем демодуляция YIQ
+    pub fn generate(settings: &PaletteSettings) -> Self {
+        let colors = (0..PALETTE_SIZE)
+            .map(|pixel| composite_color(pixel as u16, settings))
+            .collect();
+
+        Palette { colors }
+    }
+
+    //Файл .pal: 64 или 512 троек RGB. В 64-цветном файле подчеркивание
+    //достраивается ослаблением остальных каналов
+    pub fn from_pal(data: &[u8]) -> Result<Self, String> {
+        let entries = match data.len() {
+            length if length == BASE_COLORS * 3 => BASE_COLORS,
+            length if length == PALETTE_SIZE * 3 => PALETTE_SIZE,
+            length => return Err(format!("Palette size {} unsupported", length)),
+        };
+
+        let colors = (0..PALETTE_SIZE)
+            .map(|pixel| {
+                let color = match entries {
+                    PALETTE_SIZE => pixel,
+                    _ => pixel % BASE_COLORS,
+                } * 3;
+                let rgb = [data[color], data[color + 1], data[color + 2]];
+
+                match entries {
+                    PALETTE_SIZE => rgb,
+                    _ => emphasize(rgb, pixel as u16),
+                }
+            })
+            .collect();
+
+        Ok(Palette { colors })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        Palette::from_pal(&data)
+    }
+
+    pub fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colors[pixel as usize & (PALETTE_SIZE - 1)]
+    }
+
+    //Кадр PPU в RGB24
+    pub fn to_rgb(&self, frame: &[u16]) -> Vec<u8> {
+        frame.iter().flat_map(|&pixel| self.rgb(pixel)).collect()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::generate(&PaletteSettings::default())
+    }
+}
+
+fn composite_color(pixel: u16, settings: &PaletteSettings) -> [u8; 3] {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        let signal = composite_signal(pixel, phase) / 12.0;
+        let angle = PI * phase as f32 / 6.0 + settings.hue.to_radians();
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+
+    yiq_to_rgb(y, i, q, settings)
+}
+
+//Уровень сигнала пикселя в фазе поднесущей 0-11, 0.0 - черный, 1.0 - белый
+pub(crate) fn composite_signal(pixel: u16, phase: u16) -> f32 {
+    let color = pixel & 0x0F;
+    let emphasis = pixel >> 6;
+    //$xE и $xF - черный
+    let level = match color {
+        0x0E..=0x0F => 1,
+        _ => (pixel >> 4) as usize & 0x03,
+    };
+
+    //Цвет 0 - постоянно высокий уровень, $D-$F - постоянно низкий
+    let mut signal = match (color, in_color_phase(color, phase)) {
+        (0x00, _) => SIGNAL_HIGH[level],
+        (0x0D..=0x0F, _) => SIGNAL_LOW[level],
+        (_, true) => SIGNAL_HIGH[level],
+        (_, false) => SIGNAL_LOW[level],
+    };
+
+    let attenuated = (emphasis & 0x01 != 0 && in_color_phase(12, phase))
+        || (emphasis & 0x02 != 0 && in_color_phase(4, phase))
+        || (emphasis & 0x04 != 0 && in_color_phase(8, phase));
+    if attenuated && color < 0x0E {
+        signal *= EMPHASIS_ATTENUATION;
+    }
+
+    (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+//Демодулированный сигнал с ручками настройки в RGB
+pub(crate) fn yiq_to_rgb(y: f32, i: f32, q: f32, settings: &PaletteSettings) -> [u8; 3] {
+    let y = y * settings.contrast + settings.brightness;
+    let i = i * settings.saturation * settings.contrast;
+    let q = q * settings.saturation * settings.contrast;
+
+    [
+        gamma_correct(y + 0.946_882 * i + 0.623_557 * q),
+        gamma_correct(y - 0.274_788 * i - 0.635_691 * q),
+        gamma_correct(y - 1.108_545 * i + 1.709_007 * q),
+    ]
+}
+
+//Генератор цвета держит высокий уровень половину периода поднесущей,
+//сдвиг 8 выравнивает фазу с цветовой вспышкой
+fn in_color_phase(color: u16, phase: u16) -> bool {
+    (color + phase + 8) % 12 < 6
+}
+
+fn gamma_correct(value: f32) -> u8 {
+    match value <= 0.0 {
+        true => 0,
+        false => (value.powf(GAMMA) * 255.0).round().min(255.0) as u8,
+    }
+}
+
+//Подчеркивание для 64-цветных палитр: каналы, которые не подчеркнуты, темнеют
+fn emphasize(rgb: [u8; 3], pixel: u16) -> [u8; 3] {
+    let emphasis = pixel >> 6;
+    if emphasis == 0 || pixel & 0x0E == 0x0E {
+        return rgb;
+    }
+
+    let mut result = rgb;
+    for (channel, value) in result.iter_mut().enumerate() {
+        if emphasis & (1 << channel) == 0 {
+            *value = (*value as f32 * EMPHASIS_ATTENUATION).round() as u8;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod palette_test {
+    use super::*;
+
+    #[test]
+    fn test_generated_palette() {
+        let palette = Palette::default();
+
+        assert_eq!(palette.rgb(0x0F), [0, 0, 0]);
+        assert_eq!(palette.rgb(0x1D), [0, 0, 0]);
+        let white = palette.rgb(0x20);
+        assert!(white.iter().all(|&channel| channel > 0xF0));
+
+        //$16 - красный, $12 - синий
+        let red = palette.rgb(0x16);
+        assert!(red[0] > red[1] && red[0] > red[2]);
+        let blue = palette.rgb(0x12);
+        assert!(blue[2] > blue[0] && blue[2] > blue[1]);
+
+        //Подчеркивание красного в оттенках серого
+        let emphasized = palette.rgb(0x30 | 0x01 << 6);
+        assert!(emphasized[0] > emphasized[1] && emphasized[0] > emphasized[2]);
+        assert_eq!(palette.rgb(0x0F | 0x07 << 6), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_settings() {
+        let base = Palette::default();
+        let gray = Palette::generate(&PaletteSettings {
+            saturation: 0.0,
+            ..PaletteSettings::default()
+        });
+        let red = gray.rgb(0x16);
+        assert!(red[0] == red[1] && red[1] == red[2]);
+
+        let bright = Palette::generate(&PaletteSettings {
+            brightness: 0.1,
+            ..PaletteSettings::default()
+        });
+        assert!(bright.rgb(0x00)[1] > base.rgb(0x00)[1]);
+    }
+
+    #[test]
+    fn test_pal_files() {
+        let data: Vec<u8> = (0..BASE_COLORS * 3).map(|i| i as u8 | 0x80).collect();
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.rgb(0x01), [0x83, 0x84, 0x85]);
+        //Синее подчеркивание ослабляет красный и зеленый
+        assert_eq!(palette.rgb(0x01 | 0x04 << 6), [0x62, 0x62, 0x85]);
+
+        let data: Vec<u8> = (0..PALETTE_SIZE * 3).map(|i| (i / 3) as u8).collect();
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.rgb(0x41), [0x41; 3]);
+
+        assert!(Palette::from_pal(&[0; 100]).is_err());
+        assert_eq!(palette.to_rgb(&[0x01, 0x02]), vec![1, 1, 1, 2, 2, 2]);
+    }
+}
@@ -0,0 +1,140 @@
+//Периоды шумового канала в тактах CPU
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+//Периоды вывода бит DMC в тактах CPU
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+//Шаги счетчика кадров APU в тактах CPU: четыре шага и пятый шаг 5-шагового режима
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+//Телевизионный стандарт приставки. Dendy - PAL-совместимый клон:
+//частоты PAL, но тайминги CPU/APU как у NTSC и длинный пост-рендер
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+#[allow(dead_code)]
+impl Region {
+    //Байт 12 заголовка NES 2.0: 0 - NTSC, 1 - PAL, 2 - любой, 3 - Dendy
+    pub fn from_nes2_timing(timing: u8) -> Self {
+        match timing & 0x03 {
+            1 => Region::Pal,
+            3 => Region::Dendy,
+            _ => Region::Ntsc,
+        }
+    }
+
+    pub fn master_clock(&self) -> u32 {
+        match self {
+            Region::Ntsc => 21_477_272,
+            Region::Pal | Region::Dendy => 26_601_712,
+        }
+    }
+
+    //Делители мастер-частоты для CPU и PPU, PPU:CPU = 3, 3.2 или 3
+    pub fn cpu_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    pub fn ppu_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.master_clock() as f64 / self.cpu_divider() as f64
+    }
+
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    //Первая строка vblank: у Dendy 51 строка пост-рендера,
+    //поэтому vblank такой же короткий, как у NTSC
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    pub fn pre_render_scanline(&self) -> u16 {
+        self.scanlines() - 1
+    }
+
+    pub fn vblank_lines(&self) -> u16 {
+        self.pre_render_scanline() - self.vblank_scanline()
+    }
+
+    //Короткая строка подготовки в нечетных кадрах есть только у 2C02
+    pub fn skips_odd_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    pub fn noise_periods(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_NOISE_PERIODS,
+            Region::Pal => &PAL_NOISE_PERIODS,
+        }
+    }
+
+    pub fn dmc_rates(&self) -> &'static [u16; 16] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_DMC_RATES,
+            Region::Pal => &PAL_DMC_RATES,
+        }
+    }
+
+    pub fn frame_counter_steps(&self) -> &'static [u32; 5] {
+        match self {
+            Region::Ntsc | Region::Dendy => &NTSC_FRAME_STEPS,
+            Region::Pal => &PAL_FRAME_STEPS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod region_test {
+    use super::*;
+
+    #[test]
+    fn test_timing() {
+        assert_eq!(Region::from_nes2_timing(0), Region::Ntsc);
+        assert_eq!(Region::from_nes2_timing(1), Region::Pal);
+        assert_eq!(Region::from_nes2_timing(2), Region::Ntsc);
+        assert_eq!(Region::from_nes2_timing(3), Region::Dendy);
+
+        assert_eq!(Region::Ntsc.vblank_lines(), 20);
+        assert_eq!(Region::Pal.vblank_lines(), 70);
+        assert_eq!(Region::Dendy.vblank_lines(), 20);
+
+        //Около 1.79, 1.66 и 1.77 МГц
+        assert_eq!(Region::Ntsc.cpu_clock_rate() as u32, 1_789_772);
+        assert_eq!(Region::Pal.cpu_clock_rate() as u32, 1_662_607);
+        assert_eq!(Region::Dendy.cpu_clock_rate() as u32, 1_773_447);
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Png,
+    //Двоичный PPM (P6), без зависимостей
+    Ppm,
+}
+
+#[allow(dead_code)]
+impl ImageFormat {
+    //Формат по расширению файла, по умолчанию PNG
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("ppm") => ImageFormat::Ppm,
+            _ => ImageFormat::Png,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+//Кодирует кадр RGB24
+pub fn encode(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    format: ImageFormat,
+) -> Result<Vec<u8>, String> {
+    if rgb.len() != width * height * 3 {
+        return Err(format!(
+            "Frame size {} does not match {}x{}",
+            rgb.len(),
+            width,
+            height
+        ));
+    }
+
+    match format {
+        ImageFormat::Png => encode_png(rgb, width, height),
+        ImageFormat::Ppm => Ok(encode_ppm(rgb, width, height)),
+    }
+}
+
+fn encode_png(rgb: &[u8], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut data, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(rgb).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+
+    Ok(data)
+}
+
+fn encode_ppm(rgb: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    data.extend_from_slice(rgb);
+    data
+}
+
+#[cfg(test)]
+mod screenshot_test {
+    use super::*;
+
+    #[test]
+    fn test_ppm() {
+        let data = encode(&[1, 2, 3, 4, 5, 6], 2, 1, ImageFormat::Ppm).unwrap();
+        assert_eq!(data, b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06".to_vec());
+
+        assert!(encode(&[0; 5], 2, 1, ImageFormat::Ppm).is_err());
+    }
+
+    #[test]
+    fn test_png() {
+        let rgb: Vec<u8> = (0..4 * 2 * 3).map(|i| i as u8).collect();
+        let data = encode(&rgb, 4, 2, ImageFormat::Png).unwrap();
+
+        let decoder = png::Decoder::new(data.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut decoded).unwrap();
+
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(&decoded[..info.buffer_size()], rgb.as_slice());
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ImageFormat::from_path("shot.PPM"), ImageFormat::Ppm);
+        assert_eq!(ImageFormat::from_path("shot.png"), ImageFormat::Png);
+        assert_eq!(ImageFormat::from_path("shot"), ImageFormat::Png);
+    }
+}